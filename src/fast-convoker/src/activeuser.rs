@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+
+use chrono::{Date, Duration, Datelike};
+use chrono::offset::TimeZone;
+use chrono_tz::Tz;
+use spet::span::CreatableSpan;
+use spet::span::Span;
+use spet::vecspet::VecSpet;
+
+use shmeppy_log::daterange::DateRange;
+use shmeppy_log::ids::{GameId, UserId};
+use shmeppy_log::zones::start_of_day;
+
+use crate::timespan::TimeSpan;
+use crate::request::Request;
+use crate::globalpresence::collect_global_presences;
+
+
+fn total_time(spet: &VecSpet<TimeSpan>) -> Duration {
+    spet.into_iter().fold(Duration::zero(), |a, i| a + (*i.end() - *i.start()))
+}
+
+
+/**
+ * Works out how many seconds each GM spent in an active game on each day.
+ *
+ * The result looks like {day: {user_id: active_seconds}}, where the days are
+ * in report_tz. Every day from the start of 2018 through as_of is there,
+ * or just the ones in dates if it's narrower. Without an as_of there's
+ * nothing to go up to, and so no days at all.
+ */
+pub fn collect_active_users(
+        requests: impl Iterator<Item = Request>,
+        activity: &BTreeMap<GameId, VecSpet<TimeSpan>>,
+        report_tz: Tz,
+        dates: &DateRange,
+        as_of: Option<Date<Tz>>)
+        -> BTreeMap<String, BTreeMap<UserId, i64>> {
+    // Unlike in the fast-convoker, we take into account the activity data
+    // when we generate our presences. This is because, unlike in
+    // fast-convoker, we're not keying presences on game ID.
+    let global_presences = collect_global_presences(
+        requests.filter(|r| r.is_admin),
+        activity);
+
+    let mut result: BTreeMap<String, BTreeMap<UserId, i64>> = BTreeMap::new();
+    let as_of = match as_of {
+        Some(as_of) => as_of,
+        None => return result,
+    };
+
+    let (mut current_day, last_day) =
+        dates.clamp(report_tz.ymd(2018, 1, 1), as_of);
+    while current_day <= last_day {
+        // Days aren't always 24 hours long once DST gets involved
+        let day_spet = VecSpet::<TimeSpan>::from_sorted_iter(vec![
+            TimeSpan::new(
+                start_of_day(&current_day),
+                start_of_day(&current_day.succ()) - Duration::nanoseconds(1)),
+        ]);
+        let day = format!("{}-{}-{}", current_day.year(), current_day.month(),
+                          current_day.day());
+        result.insert(day.clone(), BTreeMap::new());
+
+        let user_to_seconds = result.get_mut(&day).unwrap();
+        for presence in &global_presences {
+            let seconds = total_time(&presence.spet.intersection(&day_spet)).num_seconds();
+            if seconds > 0 {
+                user_to_seconds.insert(presence.user_id, seconds);
+            }
+        }
+
+        current_day = current_day.succ();
+    }
+
+    result
+}
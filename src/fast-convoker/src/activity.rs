@@ -1,43 +1,82 @@
 use std::collections::BTreeMap;
 
 use chrono::{DateTime, Utc, Duration};
+use serde::{Deserialize, Serialize};
 use spet::vecspet::VecSpet;
 
-use crate::request::GameId;
-use crate::lex::Parts;
-use crate::parse::{UUID, parse_uuid, parse_timestamp};
+use shmeppy_log::error::Stage;
+use shmeppy_log::ids::GameId;
+use shmeppy_log::lex::Parts;
+use shmeppy_log::parse::{UUID, parse_uuid};
+use shmeppy_log::zones::Zones;
+
 use crate::accumulator::push_onto_accumulator;
 use crate::timespan::TimeSpan;
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct ActivityCollector {
     active_times: BTreeMap<UUID, Vec<DateTime<Utc>>>,
+    #[serde(skip)]
+    zones: Zones,
 }
 
 
 fn is_activity_message(msg: &[u8]) -> bool {
-    let expected_prefix = b"Committed ";
-    let expected_suffix = b" operation(s).";
-    msg[..expected_prefix.len()] == *expected_prefix &&
-        msg[msg.len() - expected_suffix.len()..] == *expected_suffix
+    msg.starts_with(b"Committed ") && msg.ends_with(b" operation(s).")
 }
 
 
 impl ActivityCollector {
     pub fn new() -> ActivityCollector {
-        ActivityCollector { active_times: BTreeMap::new() }
+        ActivityCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> ActivityCollector {
+        ActivityCollector { active_times: BTreeMap::new(), zones }
     }
 
-    pub fn update(&mut self, parts: &Parts) -> Option<()> {
-        if is_activity_message(parts.message) {
-            let uuid = parse_uuid(parts.uuid)?;
-            let timestamp = parse_timestamp(parts.timestamp)?;
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
+        if is_activity_message(&parts.message) {
+            let uuid = parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?;
+            let timestamp = self.zones.parse(parts.timestamp)
+                .ok_or(Stage::ParseTimestamp)?;
             push_onto_accumulator(&mut self.active_times, uuid, timestamp);
+        }
+
+        Ok(())
+    }
 
-            Some(())
-        } else {
-            None
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: ActivityCollector) {
+        for (uuid, timestamps) in later.active_times {
+            self.active_times.entry(uuid).or_default().extend(timestamps);
+        }
+    }
+
+    /**
+     * The last time each request committed something.
+     */
+    pub fn last_active(&self) -> BTreeMap<UUID, DateTime<Utc>> {
+        self.active_times.iter()
+            .filter_map(|(uuid, timestamps)| {
+                Some((*uuid, *timestamps.iter().max()?))
+            })
+            .collect()
+    }
+
+    /**
+     * Gives back when each request committed something, in order.
+     */
+    pub fn into_times(self) -> BTreeMap<UUID, Vec<DateTime<Utc>>> {
+        let mut active_times = self.active_times;
+        for timestamps in active_times.values_mut() {
+            timestamps.sort_unstable();
         }
+        active_times
     }
 
     pub fn into_spets(
@@ -53,19 +92,37 @@ impl ActivityCollector {
             }
         }
 
-        let mut result = BTreeMap::new();
-        for (game_id, mut timestamps) in by_game_id {
-            use spet::span::CreatableSpan;
-            timestamps.sort_unstable();
-            let spans = timestamps.into_iter().map(|timestamp|
-                TimeSpan::new(
-                    timestamp - window_size / 2,
-                    timestamp + window_size / 2));
+        activity_spets(by_game_id, window_size)
+    }
+}
 
-            result.insert(game_id, VecSpet::from_sorted_iter(spans));
-        }
 
-        result
+/**
+ * Turns each game's activity into the spans of time it was active, where
+ * each bit of activity counts for window_size around when it happened.
+ */
+pub fn activity_spets(by_game_id: BTreeMap<GameId, Vec<DateTime<Utc>>>,
+                      window_size: Duration)
+        -> BTreeMap<GameId, VecSpet<TimeSpan>> {
+    let mut result = BTreeMap::new();
+    for (game_id, mut timestamps) in by_game_id {
+        use spet::span::CreatableSpan;
+        timestamps.sort_unstable();
+        let spans = timestamps.into_iter().map(|timestamp|
+            TimeSpan::new(
+                timestamp - window_size / 2,
+                timestamp + window_size / 2));
+
+        result.insert(game_id, VecSpet::from_sorted_iter(spans));
+    }
+
+    result
+}
+
+
+impl Default for ActivityCollector {
+    fn default() -> ActivityCollector {
+        ActivityCollector::new()
     }
 }
 
@@ -77,9 +134,17 @@ mod tests {
     use chrono::offset::TimeZone;
     use chrono::{Utc, Duration};
 
-    use crate::parse::UUID;
-    use crate::activity::{ActivityCollector, TimeSpan};
-    use crate::lex::locate_parts;
+    use shmeppy_log::parse::UUID;
+    use shmeppy_log::lex::locate_parts;
+    use crate::activity::{ActivityCollector, TimeSpan, is_activity_message};
+
+    #[test]
+    fn short_messages() {
+        assert!(is_activity_message(b"Committed 1 operation(s)."));
+        assert!(!is_activity_message(b"Commit"));
+        assert!(!is_activity_message(b""));
+        assert!(!is_activity_message(b"Committed 1 operation"));
+    }
 
     #[test]
     fn standard_use() {
@@ -91,7 +156,7 @@ mod tests {
 
         let mut collector = ActivityCollector::new();
         for line in lines {
-            collector.update(&locate_parts(line).unwrap());
+            collector.update(&locate_parts(line).unwrap()).unwrap();
         }
 
         assert_eq!(
@@ -0,0 +1,1400 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Condvar, Mutex, mpsc};
+use std::thread;
+
+use chrono::{Date, DateTime, Duration, Utc};
+use chrono::offset::TimeZone;
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use spet::vecspet::VecSpet;
+
+use shmeppy_log::daterange::DateRange;
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::{Error, Stage};
+use shmeppy_log::event::EventCollector;
+use shmeppy_log::feed::{Feed, Feeder, Skipped};
+use shmeppy_log::ids::{GameId, UserId};
+use shmeppy_log::input::{Source, collect_sources};
+use shmeppy_log::lex::Parts;
+use shmeppy_log::registration::RegistrationCollector;
+use shmeppy_log::zones::Zones;
+
+use crate::accumulator::push_onto_accumulator;
+use crate::activeuser::collect_active_users;
+use crate::compact::CompactActiveUsers;
+use crate::activity::{ActivityCollector, activity_spets};
+use crate::checkpoint::{Checkpoints, Key};
+use crate::closecode::{RoleCloseCodes, collect_close_codes};
+use crate::convocation::{Convocation, ConvocationRules, collect_convocations};
+use crate::dataset::read_dataset;
+use crate::diagnostics::{Diagnostics, DiagnosticsCollector, rejected_stage};
+use crate::endpoint::{EndpointCollector, EndpointStats};
+use crate::extract::RequestRecord;
+use crate::options::Options;
+use crate::output::write_atomically;
+use crate::protocolversion::{VersionCounts, collect_protocol_versions};
+use crate::reconnect::{ConvocationReconnects, collect_reconnects};
+use crate::request::{InferredEnds, OpenRequest, Request, RequestCollector};
+use crate::timespan::TimeSpan;
+use crate::websocket::WebsocketCollector;
+
+
+/**
+ * One of the datasets we can produce from the logs.
+ */
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy)]
+pub enum Analysis {
+    Convocations,
+    ActiveUsers,
+    Registrations,
+    EventCounts,
+    Websockets,
+    Reconnects,
+    ProtocolVersions,
+    CloseCodes,
+    Endpoints,
+}
+
+
+impl Analysis {
+    pub const ALL: [Analysis; 9] = [
+        Analysis::Convocations,
+        Analysis::ActiveUsers,
+        Analysis::Registrations,
+        Analysis::EventCounts,
+        Analysis::Websockets,
+        Analysis::Reconnects,
+        Analysis::ProtocolVersions,
+        Analysis::CloseCodes,
+        Analysis::Endpoints,
+    ];
+
+    /**
+     * The analyses that only need requests and activity, and so can be
+     * worked out from a dataset.
+     */
+    pub const FROM_DATASET: [Analysis; 5] = [
+        Analysis::Convocations,
+        Analysis::ActiveUsers,
+        Analysis::Reconnects,
+        Analysis::ProtocolVersions,
+        Analysis::CloseCodes,
+    ];
+
+    /**
+     * Whether this is worked out from requests and activity (rather than
+     * straight from the log lines).
+     */
+    pub fn needs_requests(self) -> bool {
+        Analysis::FROM_DATASET.contains(&self)
+    }
+
+    /**
+     * The name used to pick this analysis on the command line.
+     */
+    pub fn name(self) -> &'static str {
+        match self {
+            Analysis::Convocations => "convocations",
+            Analysis::ActiveUsers => "active-users",
+            Analysis::Registrations => "registrations",
+            Analysis::EventCounts => "event-counts",
+            Analysis::Websockets => "websockets",
+            Analysis::Reconnects => "reconnects",
+            Analysis::ProtocolVersions => "protocol-versions",
+            Analysis::CloseCodes => "close-codes",
+            Analysis::Endpoints => "endpoints",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Analysis> {
+        Analysis::ALL.iter().copied().find(|analysis| analysis.name() == name)
+    }
+
+    /**
+     * The file the frontend expects this analysis to be written to.
+     */
+    pub fn file_name(self) -> String {
+        format!("{}.json", self.name())
+    }
+}
+
+
+/**
+ * The latest line a pipeline's been fed, going by its timestamp.
+ *
+ * This is noted for every line, so the timestamp's kept raw and only parsed
+ * when someone asks for it. Lines from within a day are assumed to be in
+ * order, which they are in any one log file.
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct LastSeen {
+    day: Option<DateString>,
+    raw: Vec<u8>,
+}
+
+
+impl LastSeen {
+    fn update(&mut self, raw: &[u8]) {
+        if let Some(day) = DateString::from_timestamp(raw) {
+            if self.day.is_none_or(|last| last <= day) {
+                self.day = Some(day);
+                self.raw.clear();
+                self.raw.extend_from_slice(raw);
+            }
+        }
+    }
+
+    fn at(&self, zones: &Zones) -> Option<DateTime<Utc>> {
+        zones.parse(&self.raw)
+    }
+
+    fn merge(&mut self, later: LastSeen, zones: &Zones) {
+        if later.at(zones) >= self.at(zones) {
+            *self = later;
+        }
+    }
+}
+
+
+/**
+ * Feeds each log line to every collector the requested analyses need.
+ *
+ * Each line is only split and lexed once no matter how many analyses are
+ * running, which is the whole point of running them together.
+ *
+ * Only the collectors are checkpointed. Everything else comes from the
+ * options, and isn't saved with them.
+ */
+#[derive(Serialize, Deserialize)]
+pub struct Pipeline {
+    // Everything that needs requests (see Analysis::needs_requests) is
+    // built from the same requests and activity, so they share these.
+    websockets: Option<(RequestCollector, ActivityCollector)>,
+    registrations: Option<RegistrationCollector>,
+    events: Option<EventCollector>,
+    websocket_counts: Option<WebsocketCollector>,
+    endpoints: Option<EndpointCollector>,
+    #[serde(skip)]
+    analyses: Vec<Analysis>,
+    #[serde(skip)]
+    zones: Zones,
+    diagnostics: Option<DiagnosticsCollector>,
+    feeder: Feeder,
+
+    // Whether the report should hang onto every request, which only the
+    // SQLite output wants
+    #[serde(skip)]
+    keep_requests: bool,
+
+    // The day to report up through, if it was given. Otherwise it's the day
+    // of the last line we saw, so the same logs always give the same report.
+    #[serde(skip)]
+    as_of: Option<DateString>,
+    last_seen: LastSeen,
+
+    // How long requests that never finished can go quiet before they're
+    // taken to have ended (see RequestCollector::infer_ends)
+    #[serde(skip)]
+    open_timeout: Option<Duration>,
+    #[serde(skip)]
+    convocation_rules: ConvocationRules,
+}
+
+
+impl Pipeline {
+    pub fn new(analyses: &[Analysis], options: &Options) -> Pipeline {
+        let wants = |analysis| analyses.contains(&analysis);
+        let zones = options.zones;
+        Pipeline {
+            websockets:
+                if analyses.iter().any(|analysis| analysis.needs_requests()) {
+                    Some((RequestCollector::with_zones(zones),
+                          ActivityCollector::with_zones(zones)))
+                } else {
+                    None
+                },
+            registrations: if wants(Analysis::Registrations) {
+                Some(RegistrationCollector::with_zones(zones))
+            } else {
+                None
+            },
+            events: if wants(Analysis::EventCounts) {
+                Some(EventCollector::with_zones(zones))
+            } else {
+                None
+            },
+            websocket_counts: if wants(Analysis::Websockets) {
+                Some(WebsocketCollector::with_zones(zones))
+            } else {
+                None
+            },
+            endpoints: if wants(Analysis::Endpoints) {
+                Some(EndpointCollector::with_zones(zones))
+            } else {
+                None
+            },
+            analyses: analyses.to_vec(),
+            zones,
+            // Diagnostics are only worth their cost if someone's going to
+            // look at them
+            diagnostics: if options.diagnostics.is_some() {
+                Some(DiagnosticsCollector::new())
+            } else {
+                None
+            },
+            feeder: Feeder::new(options.filter.clone(), options.strict),
+            keep_requests: options.sqlite.is_some(),
+            as_of: options.as_of,
+            last_seen: LastSeen::default(),
+            open_timeout: options.open_timeout
+                .and_then(|timeout| Duration::from_std(timeout).ok()),
+            convocation_rules: options.convocation_rules,
+        }
+    }
+
+    /**
+     * Hands a line to every collector, and gives back the earliest stage
+     * any of them rejected it at. This doesn't check the line against the
+     * filter, since feed_line already has by the time it gets here.
+     */
+    pub fn update(&mut self, parts: &Parts) -> Option<Stage> {
+        self.last_seen.update(parts.timestamp);
+        let mut rejected = None;
+        if let Some((requests, activity)) = &mut self.websockets {
+            rejected = earliest(rejected, requests.update(parts));
+            rejected = earliest(rejected, activity.update(parts));
+        }
+
+        if let Some(registrations) = &mut self.registrations {
+            rejected = earliest(rejected, registrations.update(parts));
+        }
+
+        if let Some(events) = &mut self.events {
+            rejected = earliest(rejected, events.update(parts));
+        }
+
+        if let Some(websocket_counts) = &mut self.websocket_counts {
+            rejected = earliest(rejected, websocket_counts.update(parts));
+        }
+
+        if let Some(endpoints) = &mut self.endpoints {
+            rejected = earliest(rejected, endpoints.update(parts));
+        }
+
+        rejected
+    }
+
+    /**
+     * Folds in a pipeline that was fed the sources right after the ones this
+     * one was fed. Both need to have been made with the same analyses and
+     * options.
+     */
+    pub fn merge(&mut self, later: Pipeline) {
+        // Both sides were made from the same options, so they'll both either
+        // have or not have each collector.
+        if let (Some((requests, activity)), Some((later_requests, later_activity))) =
+                (&mut self.websockets, later.websockets) {
+            requests.merge(later_requests);
+            activity.merge(later_activity);
+        }
+
+        if let (Some(registrations), Some(later_registrations)) =
+                (&mut self.registrations, later.registrations) {
+            registrations.merge(later_registrations);
+        }
+
+        if let (Some(events), Some(later_events)) =
+                (&mut self.events, later.events) {
+            events.merge(later_events);
+        }
+
+        if let (Some(websocket_counts), Some(later_websocket_counts)) =
+                (&mut self.websocket_counts, later.websocket_counts) {
+            websocket_counts.merge(later_websocket_counts);
+        }
+
+        if let (Some(endpoints), Some(later_endpoints)) =
+                (&mut self.endpoints, later.endpoints) {
+            endpoints.merge(later_endpoints);
+        }
+
+        if let (Some(diagnostics), Some(later_diagnostics)) =
+                (&mut self.diagnostics, later.diagnostics) {
+            diagnostics.merge(later_diagnostics);
+        }
+
+        self.feeder.merge(later.feeder);
+        self.last_seen.merge(later.last_seen, &self.zones);
+    }
+
+    /**
+     * Drops lines belonging to requests that started before anything this
+     * pipeline was fed. Only call this once nothing earlier will be merged
+     * in.
+     */
+    pub fn forget_orphans(&mut self) {
+        if let Some((requests, _)) = &mut self.websockets {
+            requests.forget_orphans();
+        }
+
+        if let Some(registrations) = &mut self.registrations {
+            registrations.forget_orphans();
+        }
+
+        if let Some(endpoints) = &mut self.endpoints {
+            endpoints.forget_orphans();
+        }
+    }
+
+    /**
+     * Takes the requests and activity out of the pipeline, leaving it to
+     * report on everything else. Only there if an analysis that needs them
+     * was asked for.
+     */
+    pub fn take_requests(&mut self)
+            -> Option<(RequestCollector, ActivityCollector)> {
+        self.websockets.take()
+    }
+
+    /**
+     * Requests that never finished and haven't logged anything since before
+     * this are taken to have ended at their last line. How long they've
+     * been quiet is measured back from open_until if it's given, and from
+     * the last line read otherwise. None unless --open-timeout was given.
+     */
+    pub fn quiet_before(&self, open_until: Option<DateTime<Utc>>)
+            -> Option<DateTime<Utc>> {
+        Some(open_until.or_else(|| self.last_seen.at(&self.zones))? -
+             self.open_timeout?)
+    }
+
+    pub fn finish(self) -> Report {
+        let cut_off_at = if self.feeder.past_until {
+            self.feeder.filter.dates.end_in(self.zones.log)
+        } else {
+            None
+        };
+
+        let mut report = self.finish_at(cut_off_at);
+        report.cut_off_at = cut_off_at;
+        report
+    }
+
+    /**
+     * Makes a report from everything fed in so far, without giving up on
+     * feeding more later.
+     *
+     * Requests that are still open are treated as if they finished now, and
+     * any convocations they're part of are marked provisional.
+     */
+    pub fn snapshot(&self, now: DateTime<Utc>) -> Report {
+        Pipeline {
+            websockets: self.websockets.clone(),
+            registrations: self.registrations.clone(),
+            events: self.events.clone(),
+            websocket_counts: self.websocket_counts.clone(),
+            endpoints: self.endpoints.clone(),
+            analyses: self.analyses.clone(),
+            zones: self.zones,
+            diagnostics: self.diagnostics.clone(),
+            feeder: Feeder {
+                filter: self.feeder.filter.clone(),
+                strict: self.feeder.strict,
+                skipped: Skipped {
+                    lines: self.feeder.skipped.lines.clone(),
+                    // Errors can't be copied, and are only reported at the
+                    // very end anyways
+                    unreadable: Vec::new(),
+                },
+                past_until: self.feeder.past_until,
+            },
+            keep_requests: self.keep_requests,
+            as_of: self.as_of,
+            last_seen: self.last_seen.clone(),
+            open_timeout: self.open_timeout,
+            convocation_rules: self.convocation_rules,
+        }.finish_at(Some(now))
+    }
+
+    // Requests a restart cut off (or that went quiet for longer than
+    // --open-timeout) are given inferred ends. When open_until is given, the
+    // ones that are still open after that are treated as if they ended then.
+    // Otherwise they're left out, and only reported as open.
+    fn finish_at(self, open_until: Option<DateTime<Utc>>) -> Report {
+        let mut report = Report::default();
+        let zones = self.zones;
+        let last_seen = self.last_seen.at(&zones)
+            .map(|at| zones.report_date(at));
+        let as_of = as_of_date(self.as_of, zones.report).or(last_seen);
+        let quiet_before = self.quiet_before(open_until);
+
+        if let Some((mut request_collector, activity_collector)) = self.websockets {
+            report.inferred_ends = request_collector.infer_ends(
+                &activity_collector.last_active(), quiet_before);
+
+            // I don't particularly like having the activity collector relying
+            // on the request collector to get the game_id. But I also don't
+            // want to repeat work in the hot-ass loop... so this is an
+            // acceptable trade-off for the performance I think.
+            let activity = activity_collector.into_spets(
+                |game_id| request_collector.game_id_for_request(game_id),
+                Duration::minutes(ACTIVITY_WINDOW_MINUTES));
+            report.open_requests = request_collector.open_requests();
+            let mut requests: Vec<Request> =
+                request_collector.into_requests().collect();
+            if let Some(end) = open_until {
+                requests.extend(report.open_requests.iter()
+                    .map(|open| open.until(end)));
+            }
+
+            if self.keep_requests {
+                report.requests = Some(requests.clone());
+            }
+            report_on_requests(&mut report, &self.analyses, requests,
+                               &activity, self.zones.report,
+                               &self.feeder.filter.dates, as_of,
+                               &self.convocation_rules);
+            if let (Some(convocations), Some(_)) =
+                    (&mut report.convocations, open_until) {
+                mark_provisional(convocations, &report.open_requests);
+            }
+        }
+
+        report.registrations =
+            self.registrations.map(|collector| collector.into_counts());
+        report.event_counts =
+            self.events.map(|collector| collector.into_counts());
+        report.websockets =
+            self.websocket_counts.map(|collector| collector.into_counts());
+        report.endpoints =
+            self.endpoints.map(|collector| collector.into_stats());
+        report.diagnostics =
+            self.diagnostics.map(|collector| collector.into_diagnostics());
+        report.skipped = self.feeder.skipped;
+
+        report
+    }
+}
+
+
+impl Feed for Pipeline {
+    fn feeder(&mut self) -> &mut Feeder {
+        &mut self.feeder
+    }
+
+    fn handle(&mut self, parts: &Parts) -> Option<Stage> {
+        self.update(parts)
+    }
+
+    // Checking every part of every line up front repeats what the
+    // collectors do, so it's only done when every bad line has to be
+    // noticed. Otherwise the collectors say what they tripped over.
+    fn check(&mut self, source: &Source, line_number: usize, line: &[u8],
+             maybe_parts: Option<&Parts>) -> Option<Stage> {
+        if self.diagnostics.is_none() && !self.feeder.strict {
+            return maybe_parts.map_or(Some(Stage::LocateParts), |_| None);
+        }
+
+        let rejected = rejected_stage(line, maybe_parts, &self.zones);
+        if let Some(diagnostics) = &mut self.diagnostics {
+            diagnostics.update(source, line_number, line, maybe_parts,
+                               rejected);
+        }
+        rejected
+    }
+}
+
+
+// Whichever stage came first, out of what's been rejected so far and what
+// a collector just gave back
+fn earliest(rejected: Option<Stage>, result: Result<(), Stage>)
+        -> Option<Stage> {
+    match (rejected, result) {
+        (Some(rejected), Err(stage)) => Some(rejected.min(stage)),
+        (rejected, result) => rejected.or(result.err()),
+    }
+}
+
+
+// How long around each bit of activity a game counts as active
+const ACTIVITY_WINDOW_MINUTES: i64 = 30;
+
+
+// --as-of's day, in report_tz like every other day we report on
+fn as_of_date(as_of: Option<DateString>, report_tz: Tz) -> Option<Date<Tz>> {
+    Some(report_tz.from_utc_date(&as_of?.to_date()?))
+}
+
+
+// Fills in the analyses that are worked out from requests and activity
+#[allow(clippy::too_many_arguments)]
+fn report_on_requests(report: &mut Report, analyses: &[Analysis],
+                      requests: Vec<Request>,
+                      activity: &BTreeMap<GameId, VecSpet<TimeSpan>>,
+                      report_tz: Tz, dates: &DateRange,
+                      as_of: Option<Date<Tz>>, rules: &ConvocationRules) {
+    // Reconnects are counted per convocation, so they need them worked out
+    // even if they weren't asked for
+    let wants_reconnects = analyses.contains(&Analysis::Reconnects);
+    if analyses.contains(&Analysis::Convocations) || wants_reconnects {
+        let mut convocations = collect_convocations(
+            requests.iter().cloned(), activity, report_tz, rules);
+        mark_end_inferred(&mut convocations, &requests);
+        if wants_reconnects {
+            report.reconnects =
+                Some(collect_reconnects(&convocations, &requests));
+        }
+        if analyses.contains(&Analysis::Convocations) {
+            report.convocations = Some(convocations);
+        }
+    }
+
+    if analyses.contains(&Analysis::ProtocolVersions) {
+        report.protocol_versions =
+            Some(collect_protocol_versions(&requests, report_tz));
+    }
+
+    if analyses.contains(&Analysis::CloseCodes) {
+        report.close_codes = Some(collect_close_codes(&requests, report_tz));
+    }
+
+    if analyses.contains(&Analysis::ActiveUsers) {
+        report.active_users = Some(collect_active_users(
+            requests.into_iter(), activity, report_tz, dates, as_of));
+    }
+}
+
+
+// A convocation's end (or who was in it) was guessed at if any of its
+// game's requests with an inferred end overlap it
+fn mark_end_inferred(convocations: &mut BTreeMap<String, Vec<Convocation>>,
+                     requests: &[Request]) {
+    use spet::span::Span;
+
+    let inferred: Vec<&Request> =
+        requests.iter().filter(|request| request.end_inferred).collect();
+    for convocation in convocations.values_mut().flatten() {
+        convocation.end_inferred = inferred.iter().any(|request| {
+            request.game_id == convocation.game_id &&
+                request.start <= *convocation.during.end() &&
+                request.end >= *convocation.during.start()
+        });
+    }
+}
+
+
+// A convocation's still going if any of its game's open requests started
+// before it ended (they're all open until now, and so overlap it).
+fn mark_provisional(convocations: &mut BTreeMap<String, Vec<Convocation>>,
+                    open_requests: &[OpenRequest]) {
+    use spet::span::Span;
+
+    for convocation in convocations.values_mut().flatten() {
+        convocation.provisional = open_requests.iter().any(|open| {
+            open.game_id == convocation.game_id &&
+                open.start <= *convocation.during.end()
+        });
+    }
+}
+
+
+/**
+ * Runs analyses over the logs at paths, the way options says to.
+ *
+ * This is everything the binaries have in common: it writes out the
+ * diagnostics and SQLite database if they were asked for, and warns on
+ * stderr about anything that had to be skipped.
+ */
+pub fn run(analyses: &[Analysis], mut paths: impl Iterator<Item = String>,
+           options: &Options) -> Result<Report, Error> {
+    let report = match &options.dataset {
+        Some(path) => {
+            let invalid = |message| Error::Io(
+                io::Error::new(io::ErrorKind::InvalidInput, message));
+            if paths.next().is_some() {
+                return Err(invalid("--dataset is read instead of logs, so \
+                                    no logs can be given with it"));
+            }
+            if !analyses.iter().all(|analysis| {
+                    Analysis::FROM_DATASET.contains(analysis)
+                }) {
+                return Err(invalid("only analyses built from requests can \
+                                    be worked out from a dataset"));
+            }
+
+            report_on_dataset(analyses, path, options)?
+        },
+        None => finish_run(read_logs(analyses, paths, options)?, options)?,
+    };
+
+    #[cfg(feature = "sqlite")]
+    if let Some(path) = &options.sqlite {
+        crate::sqlite::write_sqlite(&report, path)?;
+    }
+
+    Ok(report)
+}
+
+
+/**
+ * Works out analyses from a dataset written by extract-requests, rather than
+ * from the logs.
+ *
+ * Datasets only hold finished requests, so any activity from requests that
+ * were still open when it was extracted is left out.
+ *
+ * --since and --until are handled like they would be for the logs: requests
+ * that started outside of them are left out, and ones still going at the
+ * end are cut off.
+ */
+pub fn report_on_dataset(analyses: &[Analysis], path: &Path, options: &Options)
+        -> Result<Report, Error> {
+    let dates = &options.filter.dates;
+    let in_range = |at: &DateTime<Utc>| dates.contains(
+        DateString::from_date(&at.with_timezone(&options.zones.log)));
+    let cut_off_at = dates.end_in(options.zones.log);
+    let records: Vec<RequestRecord> = read_dataset(path)?.into_iter()
+        .filter(|record| in_range(&record.request.start))
+        .map(|mut record| {
+            if let Some(at) = cut_off_at {
+                record.request.end = record.request.end.min(at);
+            }
+            record
+        })
+        .collect();
+
+    let mut by_game_id = BTreeMap::new();
+    for record in &records {
+        for at in record.activity.iter().filter(|at| in_range(at)) {
+            push_onto_accumulator(&mut by_game_id, record.request.game_id, *at);
+        }
+    }
+    let activity = activity_spets(
+        by_game_id, Duration::minutes(ACTIVITY_WINDOW_MINUTES));
+
+    // There are no lines to go by, so the last thing that happened in the
+    // dataset stands in for the last one seen
+    let as_of = as_of_date(options.as_of, options.zones.report).or_else(|| {
+        let last = records.iter()
+            .flat_map(|record| {
+                record.activity.iter().chain(Some(&record.request.end))
+            })
+            .max()?;
+        Some(options.zones.report_date(*last))
+    });
+
+    let mut report = Report::default();
+    let requests: Vec<Request> =
+        records.into_iter().map(|record| record.request).collect();
+    if options.sqlite.is_some() {
+        report.requests = Some(requests.clone());
+    }
+    report_on_requests(&mut report, analyses, requests, &activity,
+                       options.zones.report, dates, as_of,
+                       &options.convocation_rules);
+    Ok(report)
+}
+
+
+/**
+ * The first half of run, for anyone who wants at the collectors before
+ * they're turned into a report.
+ */
+pub fn read_logs(analyses: &[Analysis], paths: impl Iterator<Item = String>,
+                 options: &Options) -> Result<Pipeline, Error> {
+    let sources = collect_sources(paths)?;
+    feed_in_parallel(analyses, &sources, options)
+}
+
+
+/**
+ * The second half of run.
+ */
+pub fn finish_run(pipeline: Pipeline, options: &Options)
+        -> Result<Report, Error> {
+    let report = pipeline.finish();
+
+    if let Some(path) = &options.diagnostics {
+        report.write_diagnostics(path)?;
+    }
+    if !report.skipped.is_empty() {
+        eprintln!("{}", report.skipped);
+    }
+    if report.inferred_ends.total() > 0 {
+        eprintln!("{}", report.inferred_ends);
+    }
+    match (report.open_requests.len(), report.cut_off_at) {
+        (0, _) => (),
+        (open, Some(at)) =>
+            eprintln!("{} game request(s) were still open at the end of \
+                       --until, and were cut off at {}", open, at.to_rfc3339()),
+        (open, None) =>
+            eprintln!("{} game request(s) were still open at the end of the \
+                       logs", open),
+    }
+
+    Ok(report)
+}
+
+
+/**
+ * Feeds each source to its own Pipeline, spread across options.jobs threads,
+ * and merges them all back together in the order the sources were given.
+ *
+ * Requests that span two sources (ie: when the logs were rotated mid-game)
+ * get stitched back together by the merge, so this gives the same results
+ * as feeding every source to one Pipeline. In strict mode the error is the
+ * one a single Pipeline would have hit first.
+ */
+pub fn feed_in_parallel(analyses: &[Analysis], sources: &[Source],
+                        options: &Options) -> Result<Pipeline, Error> {
+    let jobs = options.jobs.max(1);
+    let next_source = AtomicUsize::new(0);
+
+    // Set once the merge below has given up, so nobody starts reading
+    // another source that'd only be thrown away
+    let stop = AtomicBool::new(false);
+
+    // How many sources have been merged so far. Nobody gets more than
+    // `window` sources ahead of that, so one slow source can't leave every
+    // source after it sitting in memory waiting its turn.
+    let window = 2 * jobs;
+    let merged_through = (Mutex::new(0), Condvar::new());
+
+    thread::scope(|scope| {
+        // Made in here so the receiver's dropped as soon as we return, rather
+        // than once every worker's finished
+        let (sender, receiver) = mpsc::channel();
+        for _ in 0..jobs.min(sources.len()) {
+            let sender = sender.clone();
+            let next_source = &next_source;
+            let stop = &stop;
+            let merged_through = &merged_through;
+            scope.spawn(move || loop {
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let i = next_source.fetch_add(1, Ordering::Relaxed);
+                let source = match sources.get(i) {
+                    Some(source) => source,
+                    None => break,
+                };
+
+                // Sources are handed out in order, so whoever has the one
+                // the merge is waiting on is never stuck here
+                let (count, caught_up) = merged_through;
+                drop(caught_up.wait_while(count.lock().unwrap(), |merged| {
+                    i >= *merged + window && !stop.load(Ordering::Relaxed)
+                }).unwrap());
+                if stop.load(Ordering::Relaxed) {
+                    break;
+                }
+
+                let result = feed_or_restore(analyses, source, options);
+
+                // The receiver's only gone if the merge has already given
+                // up, in which case there's no point carrying on.
+                if sender.send((i, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+
+        // Pipelines finish in whatever order they like, so hold onto any
+        // that show up early until it's their turn to be merged. The window
+        // keeps there from ever being more than a few of these.
+        let mut merged = Pipeline::new(analyses, options);
+        let mut waiting = BTreeMap::new();
+        for i in 0..sources.len() {
+            let result = loop {
+                if let Some(result) = waiting.remove(&i) {
+                    break result;
+                }
+
+                let (j, result) = receiver.recv()
+                    .expect("every source gets sent back");
+                waiting.insert(j, result);
+            };
+
+            let (count, caught_up) = &merged_through;
+            match result {
+                Ok(pipeline) => {
+                    merged.merge(pipeline);
+                    merged.forget_orphans();
+                    *count.lock().unwrap() = i + 1;
+                    caught_up.notify_all();
+                },
+                Err(error) => {
+                    // Set while holding the lock so nobody waiting above can
+                    // miss it
+                    let _count = count.lock().unwrap();
+                    stop.store(true, Ordering::Relaxed);
+                    caught_up.notify_all();
+                    return Err(error);
+                },
+            }
+        }
+
+        Ok(merged)
+    })
+}
+
+
+/**
+ * Gives back a Pipeline that's been fed source, restoring it from a
+ * checkpoint if options say to keep them and source hasn't changed since.
+ */
+fn feed_or_restore(analyses: &[Analysis], source: &Source, options: &Options)
+        -> Result<Pipeline, Error> {
+    let mut pipeline = Pipeline::new(analyses, options);
+    let (checkpoints, path) = match (&options.checkpoints, source) {
+        (Some(dir), Source::File(path)) => (Checkpoints::new(dir), path),
+        _ => {
+            pipeline.feed(source)?;
+            return Ok(pipeline);
+        },
+    };
+
+    let key = Key::for_file(path, analyses, options).map_err(|error| {
+        Error::Read { source: source.clone(), error }
+    })?;
+    if let Some(saved) = checkpoints.load(&key) {
+        // The saved collectors don't know the options they were made with,
+        // so they get merged into ones that do.
+        pipeline.merge(saved);
+        return Ok(pipeline);
+    }
+
+    pipeline.feed(source)?;
+
+    // A file we couldn't read all the way through is probably still being
+    // written (or was cut short), so it's not worth remembering.
+    if pipeline.feeder.skipped.unreadable.is_empty() {
+        checkpoints.save(&key, &pipeline)?;
+    }
+
+    Ok(pipeline)
+}
+
+
+/**
+ * The results of a Pipeline. Only the analyses it was asked for are filled
+ * in.
+ */
+#[derive(Default)]
+pub struct Report {
+    pub convocations: Option<BTreeMap<String, Vec<Convocation>>>,
+    pub active_users: Option<BTreeMap<String, BTreeMap<UserId, i64>>>,
+    pub registrations: Option<BTreeMap<DateString, usize>>,
+    pub event_counts: Option<BTreeMap<&'static str, BTreeMap<DateString, usize>>>,
+    pub websockets: Option<BTreeMap<String, BTreeMap<DateString, usize>>>,
+    pub reconnects: Option<BTreeMap<String, Vec<ConvocationReconnects>>>,
+    pub protocol_versions:
+        Option<BTreeMap<String, BTreeMap<String, VersionCounts>>>,
+    pub close_codes: Option<BTreeMap<String, BTreeMap<String, RoleCloseCodes>>>,
+    pub endpoints: Option<BTreeMap<String, BTreeMap<DateString, EndpointStats>>>,
+    pub diagnostics: Option<Diagnostics>,
+    pub skipped: Skipped,
+
+    // Only filled in if an analysis that needs requests was asked for, since
+    // nothing else tracks them
+    pub open_requests: Vec<OpenRequest>,
+
+    // Every request (with open ones ending when the report was made), but
+    // only if something's going to write them to SQLite
+    pub requests: Option<Vec<Request>>,
+
+    // When requests still open at the end of --until were cut off, if any
+    // were
+    pub cut_off_at: Option<DateTime<Utc>>,
+
+    // How many requests never finished and had to have their ends guessed
+    pub inferred_ends: InferredEnds,
+}
+
+
+impl Report {
+    /**
+     * Writes out one analysis as JSON. Panics if the analysis wasn't run.
+     */
+    pub fn write(&self, analysis: Analysis, writer: impl Write)
+            -> serde_json::Result<()> {
+        use serde_json::ser::to_writer;
+        match analysis {
+            Analysis::Convocations =>
+                to_writer(writer, self.convocations.as_ref().unwrap()),
+            Analysis::ActiveUsers =>
+                to_writer(writer, self.active_users.as_ref().unwrap()),
+            Analysis::Registrations =>
+                to_writer(writer, self.registrations.as_ref().unwrap()),
+            Analysis::EventCounts =>
+                to_writer(writer, self.event_counts.as_ref().unwrap()),
+            Analysis::Websockets =>
+                to_writer(writer, self.websockets.as_ref().unwrap()),
+            Analysis::Reconnects =>
+                to_writer(writer, self.reconnects.as_ref().unwrap()),
+            Analysis::ProtocolVersions =>
+                to_writer(writer, self.protocol_versions.as_ref().unwrap()),
+            Analysis::CloseCodes =>
+                to_writer(writer, self.close_codes.as_ref().unwrap()),
+            Analysis::Endpoints =>
+                to_writer(writer, self.endpoints.as_ref().unwrap()),
+        }
+    }
+
+    /**
+     * Like write, but writes active users compactly if options asks for
+     * that.
+     */
+    pub fn write_for(&self, analysis: Analysis, options: &Options,
+                     writer: impl Write) -> serde_json::Result<()> {
+        if analysis == Analysis::ActiveUsers && options.compact_active_users {
+            let active_users = self.active_users.as_ref().unwrap();
+            serde_json::ser::to_writer(
+                writer, &CompactActiveUsers::new(active_users))
+        } else {
+            self.write(analysis, writer)
+        }
+    }
+
+    /**
+     * Writes out the diagnostics, if they were collected, to path.
+     */
+    pub fn write_diagnostics(&self, path: &Path) -> io::Result<()> {
+        match &self.diagnostics {
+            Some(diagnostics) => write_atomically(path, |writer| {
+                serde_json::ser::to_writer_pretty(writer, diagnostics)
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use shmeppy_log::error::{Error, Stage};
+    use shmeppy_log::feed::Feed;
+    use shmeppy_log::filter::Filter;
+    use shmeppy_log::input::Source;
+    use shmeppy_log::lex::{Level, locate_parts};
+    use crate::analysis::{Analysis, Pipeline, run};
+    use crate::options::Options;
+
+    // A log with a line the lexer can't handle on line 2
+    fn bad_log(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(
+            format!("fast-convoker-analysis-{}-{}", name, std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn strict_options() -> Options {
+        Options { strict: true, ..Options::default() }
+    }
+
+    const BAD_LINES: &[u8] = b"\
+shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/auth/login 1.1
+garbage
+shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/auth/login 1.1
+";
+
+    #[test]
+    fn names_round_trip() {
+        for analysis in Analysis::ALL.iter() {
+            assert_eq!(Analysis::from_name(analysis.name()), Some(*analysis));
+        }
+        assert_eq!(Analysis::from_name("all"), None);
+    }
+
+    #[test]
+    fn only_requested_analyses() {
+        let mut pipeline = Pipeline::new(&[Analysis::Registrations], &Options::default());
+        pipeline.update(&locate_parts(b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/auth/login 1.1").unwrap());
+
+        let report = pipeline.finish();
+        assert!(report.registrations.is_some());
+        assert!(report.convocations.is_none());
+        assert!(report.active_users.is_none());
+        assert!(report.event_counts.is_none());
+        assert!(report.websockets.is_none());
+        assert!(report.reconnects.is_none());
+        assert!(report.protocol_versions.is_none());
+        assert!(report.close_codes.is_none());
+        assert!(report.endpoints.is_none());
+
+        let mut written = Vec::new();
+        report.write(Analysis::Registrations, &mut written).unwrap();
+        assert_eq!(written, b"{}");
+    }
+
+    #[test]
+    fn filtered() {
+        let options = Options {
+            filter: Filter { min_level: Some(Level::Info), ..Filter::default() },
+            ..Options::default()
+        };
+        let path = bad_log("filtered", b"\
+shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [DEBUG - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/92829
+shmeppy-1 shmeppy-app: (457edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/92829
+");
+        let mut pipeline = Pipeline::new(&[Analysis::Websockets], &options);
+        pipeline.feed(&Source::File(path.clone())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let mut written = Vec::new();
+        pipeline.finish().write(Analysis::Websockets, &mut written).unwrap();
+        assert_eq!(written, br#"{"shmeppy-1":{"2020-05-26":1}}"#);
+    }
+
+    #[test]
+    fn lenient() {
+        let path = bad_log("lenient", BAD_LINES);
+        let mut pipeline = Pipeline::new(&[Analysis::EventCounts], &Options::default());
+        pipeline.feed(&Source::File(path.clone())).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let report = pipeline.finish();
+        assert_eq!(report.event_counts.unwrap()["login"].len(), 1);
+        assert_eq!(report.skipped.lines[&Stage::LocateParts], 1);
+        assert_eq!(report.skipped.to_string(),
+                   "skipped 1 bad line(s) (locate_parts: 1)");
+    }
+
+    #[test]
+    fn strict() {
+        let path = bad_log("strict", BAD_LINES);
+        let mut pipeline = Pipeline::new(&[Analysis::EventCounts], &strict_options());
+        let result = pipeline.feed(&Source::File(path.clone()));
+        std::fs::remove_file(&path).unwrap();
+
+        match result {
+            Err(Error::Rejected { line_number, stage, line, .. }) => {
+                assert_eq!(line_number, 2);
+                assert_eq!(stage, Stage::LocateParts);
+                assert_eq!(line, b"garbage");
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn collectors_report_rejections() {
+        // Neither timestamp has a day, but only the first line is one
+        // the event counts would have used
+        let path = bad_log("collectors", b"\
+shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/xx/2020 3:33:19 PM] Started: POST /api/auth/login 1.1
+shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/xx/2020 3:33:20 PM] Started: GET /api/other 1.1
+");
+        let source = Source::File(path.clone());
+
+        let mut lenient = Pipeline::new(&[Analysis::EventCounts], &Options::default());
+        lenient.feed(&source).unwrap();
+        let report = lenient.finish();
+        assert!(report.event_counts.unwrap()["login"].is_empty());
+        assert_eq!(report.skipped.to_string(),
+                   "skipped 1 bad line(s) (parse_timestamp: 1)");
+
+        // Asking for diagnostics checks every line, whether or not anything
+        // would have looked at it
+        let options = Options {
+            diagnostics: Some(path.with_extension("json")),
+            ..Options::default()
+        };
+        let mut checked = Pipeline::new(&[Analysis::EventCounts], &options);
+        checked.feed(&source).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(checked.finish().skipped.to_string(),
+                   "skipped 2 bad line(s) (parse_timestamp: 2)");
+    }
+
+    #[test]
+    fn truncated_gzip() {
+        use std::io::Write;
+        use flate2::Compression;
+        use flate2::write::GzEncoder;
+
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&BAD_LINES.repeat(100)).unwrap();
+        let compressed = encoder.finish().unwrap();
+        let path = bad_log("truncated", &compressed[..compressed.len() / 2]);
+        let source = Source::File(path.clone());
+
+        let mut lenient = Pipeline::new(&[Analysis::EventCounts], &Options::default());
+        lenient.feed(&source).unwrap();
+        assert_eq!(lenient.finish().skipped.unreadable.len(), 1);
+
+        let mut strict = Pipeline::new(&[Analysis::EventCounts], &strict_options());
+        let result = strict.feed(&source);
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(result, Err(Error::Rejected { .. }) | Err(Error::Read { .. })));
+    }
+
+    #[test]
+    fn split_across_sources() {
+        let paths = [
+            bad_log("split-0", b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 11:59:59 PM] Started: POST /api/account/register/complete/ 1.1\n"),
+            bad_log("split-1", BAD_LINES),
+            bad_log("split-2", b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:01 AM] Finished: 200 'OK'\n"),
+        ];
+        let names = || paths.iter().map(|path| path.display().to_string());
+
+        for jobs in 1..=3 {
+            let options = Options { jobs, ..Options::default() };
+            let report = run(
+                &[Analysis::Registrations, Analysis::EventCounts], names(),
+                &options).unwrap();
+
+            let mut written = Vec::new();
+            report.write(Analysis::Registrations, &mut written).unwrap();
+            assert_eq!(written, br#"{"2020-05-27":1}"#, "{} jobs", jobs);
+            assert_eq!(report.event_counts.unwrap()["login"].len(), 1);
+            assert_eq!(report.skipped.lines[&Stage::LocateParts], 1);
+        }
+
+        // The error should be the first one in the order the sources were
+        // given, no matter which thread finds it first (or how far ahead of
+        // the merge the others are let get)
+        std::fs::write(&paths[2], BAD_LINES).unwrap();
+        let results: Vec<_> = (1..=3)
+            .map(|jobs| {
+                let options = Options { jobs, ..strict_options() };
+                run(&[Analysis::EventCounts], names(), &options)
+            })
+            .collect();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        for result in results {
+            match result {
+                Err(Error::Rejected { source, .. }) =>
+                    assert_eq!(source, Source::File(paths[1].clone())),
+                other => panic!("unexpected {:?}", other.map(|_| ())),
+            }
+        }
+    }
+
+    #[test]
+    fn open_across_rotation() {
+        // Given newest first, the way a glob over rotated logs might. If
+        // these were read in that order the first request would look like
+        // it never finished.
+        let paths = [
+            bad_log("rotation-1", b"\
+shmeppy-1 shmeppy-app: (457edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:40:00 AM] Finished websocket: 1000 ''
+shmeppy-1 shmeppy-app: (557edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:41:00 AM] Started websocket: GET /game-socket/92830 1.1
+"),
+            bad_log("rotation-0", b"\
+shmeppy-1 shmeppy-app: (457edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 11:33:47 PM] Started websocket: GET /game-socket/92829 1.1
+"),
+        ];
+
+        let report = run(
+            &[Analysis::Convocations],
+            paths.iter().map(|path| path.display().to_string()),
+            &Options::default()).unwrap();
+        for path in &paths {
+            std::fs::remove_file(path).unwrap();
+        }
+
+        assert_eq!(report.open_requests.len(), 1);
+        assert_eq!(report.open_requests[0].game_id, 92830);
+        assert_eq!(report.inferred_ends.total(), 0);
+    }
+
+    #[test]
+    fn provisional() {
+        use chrono::{TimeZone, Utc};
+
+        let mut lines = Vec::new();
+        for (uuid, account_id, is_admin) in [
+                ("157edc79", "a1", true),
+                ("257edc79", "b2", false),
+                ("357edc79", "c3", false)].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/5 1.1", uuid));
+            lines.push(format!(r#"shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Client added to client DB: {{"gameId":5,"clientId":1,"account":{{"accountId":"{}","displayName":"Someone"}},"isAdmin":{}}}"#, uuid, account_id, is_admin));
+        }
+        for minute in [10, 30, 50, 70].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 {}:{:02}:00 AM] Committed 1 operation(s).", 1 + minute / 60, minute % 60));
+        }
+
+        let mut pipeline = Pipeline::new(&[Analysis::Convocations], &Options::default());
+        for line in &lines {
+            pipeline.update(&locate_parts(line.as_bytes()).unwrap());
+        }
+
+        // Nobody's left yet, so it only shows up as something in progress
+        let now = Utc.ymd(2020, 5, 26).and_hms(2, 20, 0);
+        let mut written = Vec::new();
+        pipeline.snapshot(now).write(Analysis::Convocations, &mut written).unwrap();
+        let written = String::from_utf8(written).unwrap();
+        assert!(written.contains(r#""provisional":true"#), "{}", written);
+
+        let mut written = Vec::new();
+        pipeline.finish().write(Analysis::Convocations, &mut written).unwrap();
+        assert_eq!(written, b"{}");
+    }
+
+    #[test]
+    fn date_range() {
+        use shmeppy_log::daterange::{DateRange, parse_date};
+
+        const LOG: &[u8] = b"\
+shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/25/2020 11:00:00 PM] Started websocket: GET /game-socket/1 1.1
+shmeppy-1 shmeppy-app: (257edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 11:00:00 PM] Started websocket: GET /game-socket/2 1.1
+shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 11:30:00 PM] Finished websocket: 1000 ''
+";
+        const LATER: &[u8] = b"\
+shmeppy-1 shmeppy-app: (257edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:00:00 AM] Finished websocket: 1000 ''
+";
+
+        let options = Options {
+            filter: Filter {
+                dates: DateRange {
+                    since: Some(parse_date("2020-05-26").unwrap()),
+                    until: Some(parse_date("2020-05-26").unwrap()),
+                },
+                ..Filter::default()
+            },
+            ..Options::default()
+        };
+        let report = |contents: &[&[u8]], options: &Options| {
+            let path = bad_log("date-range", &contents.concat());
+            let report = run(&[Analysis::Convocations],
+                             std::iter::once(path.display().to_string()),
+                             options).unwrap();
+            std::fs::remove_file(&path).unwrap();
+            report
+        };
+
+        // The first request started before --since, so it's never seen
+        // starting at all. The second's only open because --until hid when
+        // it finished, so it's cut off there.
+        let cut = report(&[LOG, LATER], &options);
+        assert_eq!(cut.open_requests.len(), 1);
+        assert_eq!(cut.open_requests[0].game_id, 2);
+        assert_eq!(cut.cut_off_at.unwrap().to_rfc3339(),
+                   "2020-05-27T00:00:00+00:00");
+
+        assert_eq!(cut.inferred_ends.total(), 0);
+
+        // Without anything after --until, the logs just ran out, so it's
+        // still open as far as we know
+        let ran_out = report(&[LOG], &options);
+        assert_eq!(ran_out.open_requests.len(), 1);
+        assert_eq!(ran_out.inferred_ends.total(), 0);
+        assert_eq!(ran_out.cut_off_at, None);
+
+        // Unless it'd been quiet for longer than --open-timeout by the last
+        // line, in which case that's when it ended
+        let timed_out = report(&[LOG], &Options {
+            open_timeout: Some(std::time::Duration::from_secs(20 * 60)),
+            ..options
+        });
+        assert!(timed_out.open_requests.is_empty());
+        assert_eq!(timed_out.inferred_ends.timed_out, 1);
+    }
+
+    #[test]
+    fn from_dataset() {
+        use crate::dataset::write_dataset;
+        use crate::extract::collect_request_records;
+
+        let mut lines = Vec::new();
+        for (uuid, account_id, is_admin) in [
+                ("157edc79", "a1", true),
+                ("257edc79", "b2", false),
+                ("357edc79", "c3", false)].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/5 1.1", uuid));
+            lines.push(format!(r#"shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Client added to client DB: {{"gameId":5,"clientId":1,"account":{{"accountId":"{}","displayName":"Someone"}},"isAdmin":{}}}"#, uuid, account_id, is_admin));
+            lines.push(format!("shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 3:00:00 AM] Finished websocket: 1000 ''", uuid));
+        }
+        for minute in [10, 30, 50, 70].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 {}:{:02}:00 AM] Committed 1 operation(s).", 1 + minute / 60, minute % 60));
+        }
+
+        let analyses = [Analysis::Convocations, Analysis::ActiveUsers];
+        let written = |report: crate::analysis::Report| {
+            let mut written = Vec::new();
+            for analysis in analyses.iter() {
+                report.write(*analysis, &mut written).unwrap();
+            }
+            String::from_utf8(written).unwrap()
+        };
+
+        let feed = || {
+            let mut pipeline = Pipeline::new(&analyses, &Options::default());
+            for line in &lines {
+                pipeline.update(&locate_parts(line.as_bytes()).unwrap());
+            }
+            pipeline
+        };
+        let from_logs = written(feed().finish());
+        assert!(from_logs.contains(r#""game_id":5"#));
+
+        let (requests, activity) = feed().take_requests().unwrap();
+        let mut dataset = Vec::new();
+        write_dataset(&collect_request_records(requests, activity), &mut dataset)
+            .unwrap();
+        let path = bad_log("dataset", &dataset);
+        let options = Options {
+            dataset: Some(path.clone()),
+            ..Options::default()
+        };
+        let from_dataset = run(&analyses, std::iter::empty(), &options);
+
+        // Which is the point, since the convocation rules can be played with
+        // without reading them again
+        let mut stricter = Options {
+            dataset: Some(path.clone()),
+            ..Options::default()
+        };
+        stricter.convocation_rules.min_people = 4;
+        let without_convocations =
+            run(&[Analysis::Convocations], std::iter::empty(), &stricter);
+
+        // Datasets don't have anything else in them, and replace the logs
+        let others = run(&[Analysis::Registrations], std::iter::empty(), &options);
+        let with_logs = run(&analyses, std::iter::once("logs".to_string()),
+                            &options);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(written(from_dataset.unwrap()), from_logs);
+        let mut none = Vec::new();
+        without_convocations.unwrap().write(Analysis::Convocations, &mut none)
+            .unwrap();
+        assert_eq!(none, b"{}");
+        assert!(others.is_err());
+        assert!(with_logs.is_err());
+    }
+
+    #[test]
+    fn report_zone() {
+        use shmeppy_log::zones::Zones;
+
+        let options = Options {
+            zones: Zones {
+                log: chrono_tz::Tz::UTC,
+                report: chrono_tz::America::Los_Angeles,
+            },
+            ..Options::default()
+        };
+        let mut pipeline = Pipeline::new(&[Analysis::EventCounts], &options);
+
+        // Friday night in California, but already Saturday in UTC
+        pipeline.update(&locate_parts(b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/30/2020 2:00:00 AM] Started: POST /api/auth/login 1.1").unwrap());
+
+        let mut written = Vec::new();
+        pipeline.finish().write(Analysis::EventCounts, &mut written).unwrap();
+        assert!(std::str::from_utf8(&written).unwrap()
+            .contains(r#""login":{"2020-05-29":1}"#));
+    }
+}
@@ -0,0 +1,250 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::analysis::{Analysis, Pipeline};
+use crate::options::Options;
+use crate::output::write_atomically;
+
+
+// Bump this whenever a collector's fields change, so old checkpoints get
+// thrown away rather than misread.
+const FORMAT_VERSION: u32 = 10;
+
+
+fn sha256(mut reader: impl io::Read) -> io::Result<[u8; 32]> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut reader, &mut hasher)?;
+    Ok(hasher.finalize().into())
+}
+
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+
+/**
+ * Everything a checkpoint depends on. If any of it's different the file
+ * needs to be read again.
+ *
+ * The file's contents are stood in for by its size and modification time,
+ * rather than a hash of them, so finding a checkpoint is just a stat. A log
+ * rewritten in place to exactly the same size with its modification time
+ * put back would be missed, but nothing that writes logs does that. The
+ * hash is still saved alongside, for when only the time's changed (see
+ * Checkpoints::load).
+ */
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Key {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+
+    // The analyses and options that change what the collectors hold
+    pub config: String,
+}
+
+
+impl Key {
+    pub fn for_file(path: &Path, analyses: &[Analysis], options: &Options)
+            -> io::Result<Key> {
+        let metadata = fs::metadata(path)?;
+        Ok(Key {
+            path: path.to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+            config: format!(
+                "{} {:?} {:?} {:?} {} {}", FORMAT_VERSION, analyses,
+                options.filter, options.zones, options.diagnostics.is_some(),
+                options.strict),
+        })
+    }
+}
+
+
+// Of the file as it is on disk, so we never have to decompress it just to
+// find out we've seen it before
+fn sha256_file(path: &Path) -> io::Result<[u8; 32]> {
+    sha256(BufReader::new(File::open(path)?))
+}
+
+
+/**
+ * A directory of saved Pipelines, one per log file.
+ */
+pub struct Checkpoints {
+    dir: PathBuf,
+}
+
+
+impl Checkpoints {
+    pub fn new(dir: &Path) -> Checkpoints {
+        Checkpoints { dir: dir.to_path_buf() }
+    }
+
+    // Each log file always gets the same checkpoint file, so a log that
+    // changes overwrites its old checkpoint rather than piling up new ones.
+    fn path_for(&self, key: &Key) -> PathBuf {
+        let name = sha256(key.path.to_string_lossy().as_bytes())
+            .expect("reading from a slice can't fail");
+        self.dir.join(format!("{}.checkpoint", to_hex(&name)))
+    }
+
+    /**
+     * Gives back the Pipeline saved for key, if there is one. Anything
+     * missing, stale, or unreadable is treated the same: the file just gets
+     * read again.
+     *
+     * Files are only hashed if everything but their modification time
+     * matches (ie: they were copied or touched), so the usual case of a
+     * rotated log that's been sitting there untouched is just a stat. A
+     * file whose size and modification time both match is trusted without
+     * being hashed at all.
+     */
+    pub fn load(&self, key: &Key) -> Option<Pipeline> {
+        let mut reader = BufReader::new(File::open(self.path_for(key)).ok()?);
+        let (saved_key, saved_sha256): (Key, [u8; 32]) =
+            bincode::deserialize_from(&mut reader).ok()?;
+        if saved_key.modified != key.modified {
+            let touched = Key { modified: key.modified, ..saved_key };
+            if touched != *key || sha256_file(&key.path).ok()? != saved_sha256 {
+                return None;
+            }
+        } else if saved_key != *key {
+            return None;
+        }
+
+        bincode::deserialize_from(&mut reader).ok()
+    }
+
+    /**
+     * Saves pipeline as what was read out of the file key is for. Only
+     * call this once the file's been read.
+     */
+    pub fn save(&self, key: &Key, pipeline: &Pipeline) -> io::Result<()> {
+        let sha256 = sha256_file(&key.path)?;
+        fs::create_dir_all(&self.dir)?;
+        write_atomically(&self.path_for(key), |writer| {
+            bincode::serialize_into(&mut *writer, &(key, sha256))
+                .and_then(|()| bincode::serialize_into(writer, pipeline))
+                .map_err(io::Error::other)
+        })
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, File};
+    use std::path::PathBuf;
+    use std::time::{Duration, SystemTime};
+
+    use crate::analysis::{Analysis, run};
+    use crate::checkpoint::{Checkpoints, Key};
+    use crate::options::Options;
+
+    const LOG: &[u8] = b"\
+shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 11:59:59 PM] Started: POST /api/account/register/complete/ 1.1
+shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/auth/login 1.1
+garbage
+";
+
+    const FINISH: &[u8] = b"\
+shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:01 AM] Finished: 200 'OK'
+";
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fast-convoker-checkpoint-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn restores_unchanged_files() {
+        let dir = scratch_dir("restore");
+        let logs = dir.join("logs");
+        fs::create_dir_all(&logs).unwrap();
+        // These aren't really gzipped, but they're read either way
+        let first = logs.join("0.log.gz");
+        let second = logs.join("1.log.gz");
+        fs::write(&first, LOG).unwrap();
+        fs::write(&second, FINISH).unwrap();
+
+        let options = Options {
+            checkpoints: Some(dir.join("checkpoints")),
+            ..Options::default()
+        };
+        let analyses = [Analysis::Registrations, Analysis::EventCounts];
+        let report = || {
+            let report = run(&analyses, std::iter::once(logs.display().to_string()),
+                             &options).unwrap();
+            let mut written = Vec::new();
+            for analysis in analyses.iter() {
+                report.write(*analysis, &mut written).unwrap();
+            }
+            (String::from_utf8(written).unwrap(), report.skipped.to_string())
+        };
+
+        let fresh = report();
+        assert!(fresh.0.starts_with(r#"{"2020-05-27":1}"#));
+
+        // Everything should come out of the checkpoints the second time
+        // around, and give back exactly the same thing
+        let checkpoints = Checkpoints::new(options.checkpoints.as_ref().unwrap());
+        let key = Key::for_file(&first, &analyses, &options).unwrap();
+        assert!(checkpoints.load(&key).is_some());
+        assert_eq!(report(), fresh);
+
+        // Different analyses need the file read again
+        let other = Key::for_file(&first, &[Analysis::Registrations], &options)
+            .unwrap();
+        assert!(checkpoints.load(&other).is_none());
+
+        // A file that's only been touched doesn't, since its contents are
+        // the same
+        let touch = |path: &PathBuf| {
+            File::options().write(true).open(path).unwrap()
+                .set_modified(SystemTime::now() + Duration::from_secs(60))
+                .unwrap();
+        };
+        touch(&first);
+        let touched = Key::for_file(&first, &analyses, &options).unwrap();
+        assert_ne!(touched, key);
+        assert!(checkpoints.load(&touched).is_some());
+
+        // The size and modification time are all that's checked when they
+        // match, so a file that's changed without either changing is
+        // (knowingly) taken to be the same
+        let same_size = vec![b'X'; LOG.len()];
+        fs::write(&first, &same_size).unwrap();
+        File::options().write(true).open(&first).unwrap()
+            .set_modified(key.modified).unwrap();
+        assert_eq!(Key::for_file(&first, &analyses, &options).unwrap(), key);
+        assert!(checkpoints.load(&key).is_some());
+
+        // But one that's changed and been touched does, even if it's the
+        // same size
+        let mut garbled = LOG.to_vec();
+        garbled[0] = b'X';
+        fs::write(&first, garbled).unwrap();
+        touch(&first);
+        let garbled = Key::for_file(&first, &analyses, &options).unwrap();
+        assert!(checkpoints.load(&garbled).is_none());
+        fs::write(&first, LOG).unwrap();
+
+        fs::write(&second, [FINISH, FINISH].concat()).unwrap();
+        let changed = Key::for_file(&second, &analyses, &options).unwrap();
+        assert!(checkpoints.load(&changed).is_none());
+        assert_eq!(report(), fresh);
+        assert!(checkpoints.load(&changed).is_some());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,98 @@
+use std::collections::BTreeMap;
+
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use crate::request::Request;
+
+
+/**
+ * How many of one host's websockets were closed with each code on one day,
+ * split up by whether it was an admin or a player connected.
+ */
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RoleCloseCodes {
+    pub admin: BTreeMap<String, usize>,
+    pub player: BTreeMap<String, usize>,
+}
+
+
+// What requests that never logged a close code (ie: ones with inferred
+// ends) are counted under
+const NO_CODE: &str = "none";
+
+
+/**
+ * Counts the codes game websockets were closed with on each day.
+ *
+ * The result looks like {day: {host: {"admin": {code: count}, "player":
+ * {...}}}}, where requests are counted on the day in report_tz that they
+ * ended.
+ */
+pub fn collect_close_codes(requests: &[Request], report_tz: Tz)
+        -> BTreeMap<String, BTreeMap<String, RoleCloseCodes>> {
+    let mut by_day: BTreeMap<String, BTreeMap<String, RoleCloseCodes>> =
+        BTreeMap::new();
+    for request in requests {
+        let day = request.end.with_timezone(&report_tz)
+            .format("%Y-%m-%d").to_string();
+        let code = match &request.close {
+            Some(close) => close.code.to_string(),
+            None => NO_CODE.to_string(),
+        };
+
+        let roles = by_day.entry(day).or_default()
+            .entry(request.host.clone()).or_default();
+        let codes = if request.is_admin {
+            &mut roles.admin
+        } else {
+            &mut roles.player
+        };
+        *codes.entry(code).or_insert(0) += 1;
+    }
+
+    by_day
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use shmeppy_log::ids::UserId;
+    use shmeppy_log::parse::UUID;
+    use crate::closecode::collect_close_codes;
+    use crate::request::{Close, Request, SocketQuery};
+
+    fn request(host: &str, is_admin: bool, code: Option<u16>) -> Request {
+        Request {
+            request_id: UUID(0),
+            start: Utc.ymd(2020, 5, 26).and_hms(1, 0, 0),
+            end: Utc.ymd(2020, 5, 26).and_hms(2, 0, 0),
+            game_id: 5,
+            user_id: UserId::AccountId(1),
+            is_admin,
+            query: SocketQuery::default(),
+            host: host.to_string(),
+            close: code.map(|code| Close { code, reason: String::new() }),
+            end_inferred: code.is_none(),
+        }
+    }
+
+    #[test]
+    fn by_host_and_role() {
+        let requests = vec![
+            request("shmeppy-1", true, Some(1005)),
+            request("shmeppy-1", false, Some(1005)),
+            request("shmeppy-1", false, Some(1006)),
+            request("shmeppy-1", false, Some(1006)),
+            request("shmeppy-2", true, None),
+        ];
+
+        let codes = collect_close_codes(&requests, chrono_tz::UTC);
+        assert_eq!(serde_json::to_string(&codes).unwrap(), concat!(
+            r#"{"2020-05-26":{"#,
+            r#""shmeppy-1":{"admin":{"1005":1},"player":{"1005":1,"1006":2}},"#,
+            r#""shmeppy-2":{"admin":{"none":1},"player":{}}}}"#));
+    }
+}
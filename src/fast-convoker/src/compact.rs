@@ -0,0 +1,121 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use serde::de::Error as _;
+
+use shmeppy_log::ids::UserId;
+
+
+/**
+ * Active users, written so each user ID only shows up once.
+ *
+ * Looks like {"users": [user_id, ...], "days": {day: [[user, seconds], ...]}}
+ * where user is an index into users. Users are listed in the order they
+ * were first active, so the indexes of everyone already in the list stay
+ * put as more history is added.
+ */
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompactActiveUsers {
+    pub users: Vec<UserId>,
+    pub days: BTreeMap<String, Vec<(usize, i64)>>,
+}
+
+
+impl CompactActiveUsers {
+    /**
+     * Compacts days (as made by collect_active_users).
+     */
+    pub fn new<'a>(days: impl IntoIterator<
+                       Item = (&'a String, &'a BTreeMap<UserId, i64>)>)
+            -> CompactActiveUsers {
+        // The days are only sorted as strings, which isn't the order they
+        // happened in once months and days get to two digits
+        let mut days: Vec<_> = days.into_iter().collect();
+        days.sort_by_cached_key(
+            |(day, _)| NaiveDate::parse_from_str(day, "%Y-%m-%d").ok());
+
+        let mut users = Vec::new();
+        let mut indexes: BTreeMap<UserId, usize> = BTreeMap::new();
+        let mut compacted = BTreeMap::new();
+        for (day, user_to_seconds) in days {
+            let pairs = user_to_seconds.iter()
+                .map(|(&user_id, &seconds)| {
+                    let index = *indexes.entry(user_id).or_insert_with(|| {
+                        users.push(user_id);
+                        users.len() - 1
+                    });
+                    (index, seconds)
+                })
+                .collect();
+            compacted.insert(day.clone(), pairs);
+        }
+
+        CompactActiveUsers { users, days: compacted }
+    }
+
+    /**
+     * Turns this back into {day: {user_id: seconds}}, or None if it refers
+     * to a user that isn't in the list.
+     */
+    pub fn expand(&self) -> Option<BTreeMap<String, BTreeMap<UserId, i64>>> {
+        self.days.iter()
+            .map(|(day, pairs)| {
+                let user_to_seconds = pairs.iter()
+                    .map(|&(index, seconds)| {
+                        Some((*self.users.get(index)?, seconds))
+                    })
+                    .collect::<Option<_>>()?;
+                Some((day.clone(), user_to_seconds))
+            })
+            .collect()
+    }
+}
+
+
+/**
+ * Reads active users written with --compact-active-users back into the
+ * same shape collect_active_users makes.
+ */
+pub fn read_compact_active_users(reader: impl Read)
+        -> serde_json::Result<BTreeMap<String, BTreeMap<UserId, i64>>> {
+    let compact: CompactActiveUsers = serde_json::from_reader(reader)?;
+    compact.expand().ok_or_else(
+        || serde_json::Error::custom("day refers to a user that isn't listed"))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use shmeppy_log::ids::UserId;
+    use crate::compact::{CompactActiveUsers, read_compact_active_users};
+
+    #[test]
+    fn round_trips() {
+        let mut days = BTreeMap::new();
+        let mut users = BTreeMap::new();
+        users.insert(UserId::AccountId(7), 60);
+        users.insert(UserId::AccountId(3), 30);
+        days.insert("2020-10-1".to_string(), users);
+        let mut users = BTreeMap::new();
+        users.insert(UserId::AccountId(7), 90);
+        days.insert("2020-9-30".to_string(), users);
+        days.insert("2020-10-2".to_string(), BTreeMap::new());
+
+        // 7 was active first, so gets the first index even though 3 sorts
+        // before it
+        let compact = CompactActiveUsers::new(&days);
+        let written = serde_json::to_string(&compact).unwrap();
+        assert_eq!(written, concat!(
+            r#"{"users":["account_id:7","account_id:3"],"days":{"#,
+            r#""2020-10-1":[[1,30],[0,60]],"2020-10-2":[],"#,
+            r#""2020-9-30":[[0,90]]}}"#));
+
+        assert_eq!(read_compact_active_users(written.as_bytes()).unwrap(), days);
+        assert!(read_compact_active_users(
+            r#"{"users":[],"days":{"2020-1-1":[[0,5]]}}"#.as_bytes()).is_err());
+    }
+}
@@ -0,0 +1,173 @@
+use std::collections::{BTreeSet, BTreeMap};
+
+use chrono::Duration;
+use chrono_tz::Tz;
+use spet::span::Span;
+use spet::vecspet::VecSpet;
+use spet::overlapping::n_overlapping;
+use serde::ser::{Serialize, Serializer, SerializeMap};
+
+use shmeppy_log::ids::{GameId, UserId};
+
+use crate::timespan::TimeSpan;
+use crate::request::Request;
+use crate::presence::collect_presences;
+use crate::accumulator::push_onto_accumulator;
+
+
+#[derive(Debug)]
+pub struct Convocation {
+    pub game_id: GameId,
+    pub during: TimeSpan,
+    pub admins: Vec<UserId>,
+    pub players: Vec<UserId>,
+
+    // Still going on (as far as we know) when the report was made, so its
+    // end and who's in it might change
+    pub provisional: bool,
+
+    // Someone in it never logged leaving, so when they did was guessed at
+    pub end_inferred: bool,
+}
+
+
+impl Serialize for Convocation {
+    fn serialize<S: Serializer>(&self, serializer: S)
+            -> Result<S::Ok, S::Error> {
+        let flags = self.provisional as usize + self.end_inferred as usize;
+        let mut map = serializer.serialize_map(Some(5 + flags))?;
+        map.serialize_entry("game_id", &self.game_id)?;
+        map.serialize_entry("start", &self.during.start().to_rfc3339())?;
+        map.serialize_entry("end", &self.during.end().to_rfc3339())?;
+        map.serialize_entry("admins", &self.admins)?;
+        map.serialize_entry("players", &self.players)?;
+        if self.provisional {
+            // Only written when it's true so the files don't change shape
+            // for anyone not following the logs
+            map.serialize_entry("provisional", &true)?;
+        }
+        if self.end_inferred {
+            map.serialize_entry("end_inferred", &true)?;
+        }
+        map.end()
+    }
+}
+
+
+/**
+ * What it takes for people being in a game together to count as a
+ * convocation.
+ */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ConvocationRules {
+    // How many people have to be in the game at once
+    pub min_people: usize,
+
+    // Gaps shorter than this are closed over, since they're usually just
+    // someone's connection dropping
+    pub max_gap: Duration,
+
+    // Convocations have to last longer than this to be kept
+    pub min_length: Duration,
+
+    // Convocations closer together than this are merged into one, like when
+    // a group takes a break and comes back
+    pub max_break: Duration,
+}
+
+
+impl Default for ConvocationRules {
+    fn default() -> ConvocationRules {
+        ConvocationRules {
+            min_people: 3,
+            max_gap: Duration::minutes(5),
+            min_length: Duration::minutes(40),
+            max_break: Duration::minutes(90),
+        }
+    }
+}
+
+
+/**
+ * Finds every convocation (a gathering of at least rules.min_people people
+ * in a game while something is actually happening in it), keyed by the day
+ * it started in report_tz.
+ */
+pub fn collect_convocations(
+        requests: impl Iterator<Item = Request>,
+        game_id_to_activity: &BTreeMap<GameId, VecSpet<TimeSpan>>,
+        report_tz: Tz, rules: &ConvocationRules)
+        -> BTreeMap<String, Vec<Convocation>> {
+    let all_presences = collect_presences(requests);
+
+    let mut convocations_by_day: BTreeMap<String, Vec<Convocation>> =
+            BTreeMap::new();
+    for (game_id, presences) in all_presences.iter() {
+        // An iterator of timespans where a convocation was occurring
+        let convocations_during: VecSpet<TimeSpan> =
+            VecSpet::from_sorted_iter(
+                // We start with the set containing all the times N users were
+                // present.
+                n_overlapping(rules.min_people, presences.iter().map(|presence| &presence.spet))
+                    // Cut out any time users were present but nothing was
+                    // happening in the game. This prevents users who leave their
+                    // computers on all the time from messing with my analytics.
+                    // Note: this is the same as doing it to each presence's
+                    // spet beforehand, because n_overlapping is equivalent to
+                    // (A & B) | (A & C) | (B & C) (for n = 2 over 3 elements,
+                    // but hopefully the pattern is clear). So intersecting the
+                    // result of that is the same as intersecting each
+                    // individual product (distributive laws of set operations
+                    // loosely match those of multiplication and addition
+                    // here).
+                    .intersection(
+                        game_id_to_activity.get(game_id)
+                                           .unwrap_or(&VecSpet::default()))
+                    // Now close any small gaps. We'll do another filtering of
+                    // gaps at the end so we don't end up with duplicate
+                    // convocations (like if a group takes a break for a bit
+                    // and then returns). But this is to cover mundane things
+                    // like spontaneous disconnections and such.
+                    .filter_gaps(|start, end| *end - *start < rules.max_gap)
+                    // Filter out short convocations
+                    .into_iter()
+                    .filter(|span| *span.end() - *span.start() > rules.min_length)
+            ).filter_gaps(|start, end| *end - *start < rules.max_break);
+
+        // During these set operations we've lost the information of who is
+        // participating in each convocation. Now we'll go and re-figure that
+        // out.
+        for timespan in convocations_during {
+            let mut admins: BTreeSet<UserId> = BTreeSet::new();
+            let mut players: BTreeSet<UserId> = BTreeSet::new();
+            for presence in presences {
+                let spet_span = VecSpet::from_sorted_iter(vec![timespan]);
+                if !presence.spet.intersection(&spet_span).is_empty() {
+                    if presence.is_admin {
+                        admins.insert(presence.user_id);
+                        players.remove(&presence.user_id);
+                    } else if !admins.contains(&presence.user_id) {
+                        players.insert(presence.user_id);
+                    }
+                }
+            }
+
+            if !admins.is_empty() {
+                push_onto_accumulator(
+                    &mut convocations_by_day,
+                    timespan.start().with_timezone(&report_tz)
+                        .format("%Y-%m-%d").to_string(),
+                    Convocation {
+                        game_id: *game_id,
+                        during: timespan,
+                        admins: admins.into_iter().collect(),
+                        players: players.into_iter().collect(),
+                        provisional: false,
+                        end_inferred: false,
+                    });
+            }
+        }
+    }
+
+    convocations_by_day
+}
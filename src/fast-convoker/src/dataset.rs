@@ -0,0 +1,125 @@
+use std::fs::File;
+use std::io::{self, BufReader, Write};
+use std::path::Path;
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::error::Error;
+use shmeppy_log::input::Source;
+
+use crate::extract::RequestRecord;
+use crate::request::Request;
+
+
+// Bump this whenever Request's fields change, so old datasets get refused
+// rather than misread.
+const FORMAT_VERSION: u32 = 4;
+
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    request: Request,
+
+    // In seconds since the epoch
+    activity: Vec<i64>,
+}
+
+
+/**
+ * Writes records out as a dataset, which is everything convocations and
+ * active users need from the logs and not much more.
+ */
+pub fn write_dataset(records: &[RequestRecord], writer: impl Write)
+        -> io::Result<()> {
+    let entries: Vec<Entry> = records.iter()
+        .map(|record| Entry {
+            request: record.request.clone(),
+            activity: record.activity.iter().map(|at| at.timestamp()).collect(),
+        })
+        .collect();
+    bincode::serialize_into(writer, &(FORMAT_VERSION, entries))
+        .map_err(io::Error::other)
+}
+
+
+/**
+ * Reads back a dataset written by write_dataset.
+ */
+pub fn read_dataset(path: &Path) -> Result<Vec<RequestRecord>, Error> {
+    let read_error = |error| Error::Read {
+        source: Source::File(path.to_path_buf()),
+        error,
+    };
+
+    let reader = BufReader::new(File::open(path).map_err(read_error)?);
+    let (version, entries): (u32, Vec<Entry>) =
+        bincode::deserialize_from(reader)
+            .map_err(|error| read_error(io::Error::other(error)))?;
+    if version != FORMAT_VERSION {
+        return Err(read_error(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("dataset is version {} but we need version {}, so it \
+                     needs extracting again", version, FORMAT_VERSION))));
+    }
+
+    Ok(entries.into_iter()
+        .map(|entry| RequestRecord {
+            request: entry.request,
+            activity: entry.activity.into_iter()
+                .map(|seconds| Utc.timestamp(seconds, 0))
+                .collect(),
+        })
+        .collect())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use chrono::{TimeZone, Utc};
+
+    use shmeppy_log::ids::UserId;
+    use shmeppy_log::parse::UUID;
+    use crate::dataset::{read_dataset, write_dataset};
+    use crate::extract::RequestRecord;
+    use crate::request::{Close, Request, SocketQuery};
+
+    #[test]
+    fn round_trips() {
+        let records = vec![RequestRecord {
+            request: Request {
+                request_id: UUID(0x357edc79_03fe_4787_910a_d2f14302348c),
+                start: Utc.ymd(2020, 5, 26).and_hms(1, 33, 47),
+                end: Utc.ymd(2020, 5, 26).and_hms(1, 36, 23),
+                game_id: 381468491,
+                user_id: UserId::AccountId(0xc7d585cd803aafa5),
+                is_admin: true,
+                query: SocketQuery {
+                    version: Some(2),
+                    last_seen_index: Some(328),
+                },
+                host: "shmeppy-1".to_string(),
+                close: Some(Close {
+                    code: 1001,
+                    reason: "going away".to_string(),
+                }),
+                end_inferred: true,
+            },
+            activity: vec![Utc.ymd(2020, 5, 26).and_hms(1, 35, 30)],
+        }];
+
+        let path = std::env::temp_dir().join(format!(
+            "fast-convoker-dataset-{}", std::process::id()));
+        let mut written = Vec::new();
+        write_dataset(&records, &mut written).unwrap();
+        fs::write(&path, &written).unwrap();
+        assert_eq!(read_dataset(&path).unwrap(), records);
+
+        // Anything else is refused rather than misread
+        fs::write(&path, b"garbage").unwrap();
+        assert!(read_dataset(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
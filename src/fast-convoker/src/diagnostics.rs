@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::Stage;
+use shmeppy_log::input::Source;
+use shmeppy_log::lex::Parts;
+use shmeppy_log::parse::parse_uuid;
+use shmeppy_log::zones::Zones;
+
+use crate::request::is_malformed_message;
+
+
+// How many example lines we hold on to for each stage
+const MAX_SAMPLES: usize = 5;
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sample {
+    pub source: String,
+    pub line_number: usize,
+    pub line: String,
+}
+
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct StageReport {
+    pub total: usize,
+
+    // Lines we couldn't get a date out of go under "unknown"
+    pub by_day: BTreeMap<String, usize>,
+
+    pub samples: Vec<Sample>,
+}
+
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Diagnostics {
+    // Every non-empty line we looked at, rejected or not
+    pub lines: usize,
+    pub rejected: BTreeMap<Stage, StageReport>,
+}
+
+
+/**
+ * Counts the lines rejected_stage rejects, keeping a few of each around as
+ * samples.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct DiagnosticsCollector {
+    diagnostics: Diagnostics,
+}
+
+
+/**
+ * The first stage a line fails at, if any. maybe_parts is whatever the lexer
+ * made of line, and zones says how to read its timestamp.
+ *
+ * This repeats parsing work the collectors already do, so it's only worth
+ * it when every bad line needs noticing (ie: for --diagnostics or
+ * --strict). Otherwise the collectors only report lines they tripped over
+ * themselves, and one no collector looks twice at goes uncounted.
+ */
+pub fn rejected_stage(line: &[u8], maybe_parts: Option<&Parts>, zones: &Zones)
+        -> Option<Stage> {
+    let parts = match maybe_parts {
+        Some(parts) => parts,
+        // Blank lines (ie: the one after the trailing newline) aren't worth
+        // complaining about
+        None if line.is_empty() => return None,
+        None => return Some(Stage::LocateParts),
+    };
+
+    // Lines logged outside of any request (ie: on startup) don't have a
+    // UUID at all, which is fine.
+    if !parts.uuid.is_empty() && parse_uuid(parts.uuid).is_none() {
+        Some(Stage::ParseUuid)
+    } else if zones.parse(parts.timestamp).is_none() {
+        Some(Stage::ParseTimestamp)
+    } else if is_malformed_message(&parts.message) {
+        Some(Stage::ParseMessage)
+    } else {
+        None
+    }
+}
+
+
+impl DiagnosticsCollector {
+    pub fn new() -> DiagnosticsCollector {
+        DiagnosticsCollector {
+            diagnostics: Diagnostics::default(),
+        }
+    }
+
+    /**
+     * maybe_parts is whatever the lexer made of line, and rejected is what
+     * rejected_stage made of it. line_number is 1-indexed.
+     */
+    pub fn update(&mut self, source: &Source, line_number: usize,
+                  line: &[u8], maybe_parts: Option<&Parts>,
+                  rejected: Option<Stage>) {
+        if line.is_empty() {
+            return;
+        }
+        self.diagnostics.lines += 1;
+
+        let stage = match rejected {
+            Some(stage) => stage,
+            None => return,
+        };
+        let day = maybe_parts.and_then(
+            |parts| DateString::from_timestamp(parts.timestamp));
+
+        let report = self.diagnostics.rejected.entry(stage).or_default();
+        report.total += 1;
+
+        let day = match day {
+            Some(day) => day.to_string(),
+            None => "unknown".to_string(),
+        };
+        *report.by_day.entry(day).or_insert(0) += 1;
+
+        if report.samples.len() < MAX_SAMPLES {
+            report.samples.push(Sample {
+                source: source.to_string(),
+                line_number,
+                line: String::from_utf8_lossy(line).into_owned(),
+            });
+        }
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: DiagnosticsCollector) {
+        self.diagnostics.lines += later.diagnostics.lines;
+        for (stage, later_report) in later.diagnostics.rejected {
+            let report = self.diagnostics.rejected.entry(stage).or_default();
+            report.total += later_report.total;
+            for (day, count) in later_report.by_day {
+                *report.by_day.entry(day).or_insert(0) += count;
+            }
+
+            let room = MAX_SAMPLES - report.samples.len();
+            report.samples.extend(later_report.samples.into_iter().take(room));
+        }
+    }
+
+    pub fn into_diagnostics(self) -> Diagnostics {
+        self.diagnostics
+    }
+}
+
+
+impl Default for DiagnosticsCollector {
+    fn default() -> DiagnosticsCollector {
+        DiagnosticsCollector::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use shmeppy_log::error::Stage;
+    use shmeppy_log::input::Source;
+    use shmeppy_log::lex::locate_parts;
+    use shmeppy_log::zones::Zones;
+    use crate::diagnostics::{DiagnosticsCollector, rejected_stage};
+
+    #[test]
+    fn counts_each_stage() {
+        let lines: Vec<&[u8]> = vec![
+            // Fine
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/92829",
+            b"shmeppy-1 shmeppy-app: [INFO - 5/26/2020 1:33:47 AM] Listening on port 8080",
+            b"",
+            // Can't be lexed at all
+            b"garbage",
+            // Bad UUID
+            b"shmeppy-1 shmeppy-app: (357edc79) [INFO - 5/26/2020 1:33:47 AM] Finished: 200 'OK'",
+            // Bad timestamp
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 13:33:47] Finished: 200 'OK'",
+            // Bad messages
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/abc",
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:33:47 AM] Analytics ID: nope",
+        ];
+
+        let source = Source::File("app.log".into());
+        let mut collector = DiagnosticsCollector::new();
+        for (i, line) in lines.iter().enumerate() {
+            let parts = locate_parts(line);
+            collector.update(&source, i + 1, line, parts.as_ref(),
+                             rejected_stage(line, parts.as_ref(),
+                                            &Zones::default()));
+        }
+
+        let diagnostics = collector.into_diagnostics();
+        assert_eq!(diagnostics.lines, 7);
+        assert_eq!(
+            diagnostics.rejected.iter()
+                .map(|(stage, report)| (*stage, report.total))
+                .collect::<Vec<_>>(),
+            vec![(Stage::LocateParts, 1), (Stage::ParseUuid, 1),
+                 (Stage::ParseTimestamp, 1), (Stage::ParseMessage, 2)]);
+
+        let locate = &diagnostics.rejected[&Stage::LocateParts];
+        assert_eq!(locate.by_day["unknown"], 1);
+        assert_eq!(locate.samples[0].source, "app.log");
+        assert_eq!(locate.samples[0].line_number, 4);
+        assert_eq!(locate.samples[0].line, "garbage");
+
+        let message = &diagnostics.rejected[&Stage::ParseMessage];
+        assert_eq!(message.by_day["2020-05-26"], 1);
+        assert_eq!(message.by_day["2020-05-27"], 1);
+    }
+
+    #[test]
+    fn sample_limit() {
+        let source = Source::Stdin;
+        let mut collector = DiagnosticsCollector::new();
+        for i in 0..20 {
+            collector.update(&source, i + 1, b"garbage", None,
+                             Some(Stage::LocateParts));
+        }
+
+        let diagnostics = collector.into_diagnostics();
+        let locate = &diagnostics.rejected[&Stage::LocateParts];
+        assert_eq!(locate.total, 20);
+        assert_eq!(locate.samples.len(), 5);
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::Stage;
+use shmeppy_log::lex::Parts;
+use shmeppy_log::parse::{UUID, parse_uuid, skip_prefix};
+use shmeppy_log::registration::get_request_status;
+use shmeppy_log::zones::Zones;
+
+
+/**
+ * Turns the path of a request into the endpoint it's for, by swapping out
+ * anything that looks like a parameter. /api/games/381468491 becomes
+ * /api/games/:id, account IDs become :hex and UUIDs become :uuid. The query
+ * string's dropped entirely.
+ */
+pub fn normalize_path(path: &[u8]) -> String {
+    let path = match path.iter().position(|&c| c == b'?') {
+        Some(end) => &path[..end],
+        None => path,
+    };
+
+    let segments: Vec<String> = path.split(|&c| c == b'/')
+        .map(|segment| {
+            if !segment.is_empty() && segment.iter().all(u8::is_ascii_digit) {
+                ":id".to_string()
+            } else if segment.len() == 36 && parse_uuid(segment).is_some() {
+                ":uuid".to_string()
+            } else if segment.len() >= 16 &&
+                    segment.iter().all(u8::is_ascii_hexdigit) {
+                ":hex".to_string()
+            } else {
+                String::from_utf8_lossy(segment).into_owned()
+            }
+        })
+        .collect();
+    segments.join("/")
+}
+
+
+// Pulls "METHOD /normalized/path" out of "Started: METHOD PATH 1.1"
+fn parse_started(msg: &[u8]) -> Option<String> {
+    let remainder = skip_prefix(b"Started: ", msg)?;
+    let mut words = remainder.split(|&c| c == b' ');
+    let method = std::str::from_utf8(words.next()?).ok()?;
+    let path = words.next()?;
+    if method.is_empty() || path.first() != Some(&b'/') {
+        return None;
+    }
+
+    Some(format!("{} {}", method, normalize_path(path)))
+}
+
+
+/**
+ * Everything seen of one endpoint on one day.
+ */
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+struct EndpointDay {
+    count: usize,
+    statuses: BTreeMap<u16, usize>,
+
+    // How many requests took each number of seconds. The logs only go down
+    // to the second, so this stays small, and unlike a list of every
+    // latency it can be merged without losing anything.
+    latencies: BTreeMap<i64, usize>,
+}
+
+
+impl EndpointDay {
+    fn add(&mut self, status: u16, latency: i64) {
+        self.count += 1;
+        *self.statuses.entry(status).or_insert(0) += 1;
+        *self.latencies.entry(latency).or_insert(0) += 1;
+    }
+
+    fn merge(&mut self, later: EndpointDay) {
+        self.count += later.count;
+        for (status, count) in later.statuses {
+            *self.statuses.entry(status).or_insert(0) += count;
+        }
+        for (latency, count) in later.latencies {
+            *self.latencies.entry(latency).or_insert(0) += count;
+        }
+    }
+
+    // The smallest latency at least percent of requests were at or under
+    fn percentile(&self, percent: usize) -> i64 {
+        let rank = (self.count * percent).div_ceil(100).max(1);
+        let mut seen = 0;
+        for (&latency, &count) in &self.latencies {
+            seen += count;
+            if seen >= rank {
+                return latency;
+            }
+        }
+        0
+    }
+
+    fn into_stats(self) -> EndpointStats {
+        EndpointStats {
+            count: self.count,
+            latency_seconds: Latencies {
+                p50: self.percentile(50),
+                p90: self.percentile(90),
+                p99: self.percentile(99),
+                max: self.latencies.keys().next_back().copied().unwrap_or(0),
+            },
+            statuses: self.statuses,
+        }
+    }
+}
+
+
+/**
+ * What gets reported for one endpoint on one day.
+ */
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct EndpointStats {
+    pub count: usize,
+    pub statuses: BTreeMap<u16, usize>,
+    pub latency_seconds: Latencies,
+}
+
+
+/**
+ * Latency percentiles, in whole seconds since that's all the logs have.
+ */
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct Latencies {
+    pub p50: i64,
+    pub p90: i64,
+    pub p99: i64,
+    pub max: i64,
+}
+
+
+/**
+ * Pairs up each HTTP request's Started and Finished lines, and works out
+ * how many requests each endpoint got each day, what they finished with and
+ * how long they took. Requests are counted on the day they finished.
+ *
+ * Websockets have their own Started and Finished lines, so they're never
+ * counted here.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EndpointCollector {
+    // Maps endpoints to dates to what was seen of them
+    days: BTreeMap<String, BTreeMap<DateString, EndpointDay>>,
+
+    // Requests we've seen start but not finish, with their endpoint and when
+    // they started
+    pending: BTreeMap<UUID, (String, DateTime<Utc>)>,
+
+    // The first finish of requests we didn't see start, along with its
+    // status, when it happened and the day it'd be counted on. Any of these
+    // could be the end of a request pending in an earlier chunk of logs (see
+    // merge).
+    orphans: BTreeMap<UUID, (u16, DateTime<Utc>, DateString)>,
+
+    #[serde(skip)]
+    zones: Zones,
+}
+
+
+impl EndpointCollector {
+    pub fn new() -> EndpointCollector {
+        EndpointCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> EndpointCollector {
+        EndpointCollector {
+            days: BTreeMap::new(),
+            pending: BTreeMap::new(),
+            orphans: BTreeMap::new(),
+            zones,
+        }
+    }
+
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
+        if let Some(endpoint) = parse_started(&parts.message) {
+            let uuid = parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?;
+            let at = self.zones.parse(parts.timestamp)
+                .ok_or(Stage::ParseTimestamp)?;
+            self.pending.insert(uuid, (endpoint, at));
+        } else if let Some(status) = get_request_status(&parts.message) {
+            let uuid = parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?;
+            let at = self.zones.parse(parts.timestamp)
+                .ok_or(Stage::ParseTimestamp)?;
+            let day = self.zones.day(parts.timestamp)
+                .ok_or(Stage::ParseTimestamp)?;
+            match self.pending.remove(&uuid) {
+                Some(started) => self.add(started, status, at, day),
+                None => {
+                    self.orphans.entry(uuid).or_insert((status, at, day));
+                },
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add(&mut self, (endpoint, start): (String, DateTime<Utc>),
+           status: u16, end: DateTime<Utc>, day: DateString) {
+        // Lines from different files can be a second or so out of order
+        let latency = (end - start).num_seconds().max(0);
+        self.days.entry(endpoint).or_default().entry(day).or_default()
+            .add(status, latency);
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: EndpointCollector) {
+        for (uuid, (status, at, day)) in later.orphans {
+            match self.pending.remove(&uuid) {
+                Some(started) => self.add(started, status, at, day),
+                None => {
+                    self.orphans.entry(uuid).or_insert((status, at, day));
+                },
+            }
+        }
+
+        for (endpoint, days) in later.days {
+            let by_day = self.days.entry(endpoint).or_default();
+            for (day, endpoint_day) in days {
+                by_day.entry(day).or_default().merge(endpoint_day);
+            }
+        }
+        self.pending.extend(later.pending);
+    }
+
+    /**
+     * Throws away the orphans. Once everything before this collector's lines
+     * has been merged in, nothing will ever claim them.
+     */
+    pub fn forget_orphans(&mut self) {
+        self.orphans.clear();
+    }
+
+    pub fn into_stats(self)
+            -> BTreeMap<String, BTreeMap<DateString, EndpointStats>> {
+        self.days.into_iter()
+            .map(|(endpoint, days)| {
+                let days = days.into_iter()
+                    .map(|(day, endpoint_day)| (day, endpoint_day.into_stats()))
+                    .collect();
+                (endpoint, days)
+            })
+            .collect()
+    }
+}
+
+
+impl Default for EndpointCollector {
+    fn default() -> EndpointCollector {
+        EndpointCollector::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use shmeppy_log::lex::locate_parts;
+    use crate::endpoint::{EndpointCollector, normalize_path};
+
+    #[test]
+    fn normalizes_paths() {
+        assert_eq!(normalize_path(b"/api/auth/login"), "/api/auth/login");
+        assert_eq!(normalize_path(b"/api/games/381468491/operations?since=3"),
+                   "/api/games/:id/operations");
+        assert_eq!(normalize_path(b"/api/accounts/c7d585cd803aafa5/"),
+                   "/api/accounts/:hex/");
+        assert_eq!(
+            normalize_path(b"/api/invites/357edc79-03fe-4787-910a-d2f14302348c"),
+            "/api/invites/:uuid");
+    }
+
+    #[test]
+    fn standard_use() {
+        let lines: Vec<&[u8]> = vec![
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/auth/login 1.1",
+            b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/auth/login 1.1",
+            b"shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:20 PM] Started: GET /api/games/92829 1.1",
+            b"shmeppy-1 shmeppy-app: (55e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:20 PM] Started: GET /api/games/92830 1.1",
+            // Websockets aren't HTTP requests as far as this is concerned
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 3:33:20 PM] Started websocket: GET /game-socket/92829 1.1",
+            b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 3:33:21 PM] Finished websocket: 1005 ''",
+            b"shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:20 PM] Finished: 200 'OK'",
+            b"shmeppy-1 shmeppy-app: (55e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:22 PM] Finished: 404 'Not Found'",
+            b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:21 PM] Finished: 400 'Bad Request'",
+            // Finishes the day after it started
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:01 AM] Finished: 200 'OK'",
+            // Duplicate finish shouldn't be double counted
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:02 AM] Finished: 200 'OK'",
+        ];
+
+        let collect = |lines: &[&[u8]]| {
+            let mut collector = EndpointCollector::new();
+            for line in lines {
+                collector.update(&locate_parts(line).unwrap()).unwrap();
+            }
+            collector
+        };
+        let written = |collector: EndpointCollector| {
+            serde_json::to_string(&collector.into_stats()).unwrap()
+        };
+
+        let expected = concat!(
+            r#"{"GET /api/games/:id":{"2020-05-26":{"count":2,"#,
+            r#""statuses":{"200":1,"404":1},"#,
+            r#""latency_seconds":{"p50":0,"p90":2,"p99":2,"max":2}}},"#,
+            r#""POST /api/auth/login":{"#,
+            r#""2020-05-26":{"count":1,"statuses":{"400":1},"#,
+            r#""latency_seconds":{"p50":1,"p90":1,"p99":1,"max":1}},"#,
+            r#""2020-05-27":{"count":1,"statuses":{"200":1},"#,
+            r#""latency_seconds":{"p50":30402,"p90":30402,"p99":30402,"#,
+            r#""max":30402}}}}"#);
+        assert_eq!(written(collect(&lines)), expected);
+
+        for split in 0..=lines.len() {
+            let mut merged = collect(&lines[..split]);
+            merged.forget_orphans();
+            merged.merge(collect(&lines[split..]));
+            assert_eq!(written(merged), expected, "split at {}", split);
+        }
+    }
+}
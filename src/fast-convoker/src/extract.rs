@@ -0,0 +1,227 @@
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use serde::ser::{Serialize, Serializer, SerializeMap};
+
+use crate::activity::ActivityCollector;
+use crate::dataset::write_dataset;
+use crate::request::{Request, RequestCollector};
+
+
+/**
+ * A finished request, along with every time it committed something.
+ */
+#[derive(Debug, PartialEq, Eq)]
+pub struct RequestRecord {
+    pub request: Request,
+    pub activity: Vec<DateTime<Utc>>,
+}
+
+
+impl Serialize for RequestRecord {
+    fn serialize<S: Serializer>(&self, serializer: S)
+            -> Result<S::Ok, S::Error> {
+        let request = &self.request;
+        let activity: Vec<String> =
+            self.activity.iter().map(|at| at.to_rfc3339()).collect();
+
+        let mut map = serializer.serialize_map(Some(13))?;
+        map.serialize_entry("request_id", &request.request_id.to_string())?;
+        map.serialize_entry("game_id", &request.game_id)?;
+        map.serialize_entry("user_id", &request.user_id)?;
+        map.serialize_entry("is_admin", &request.is_admin)?;
+        map.serialize_entry("version", &request.query.version)?;
+        map.serialize_entry("last_seen_index",
+                            &request.query.last_seen_index)?;
+        map.serialize_entry("host", &request.host)?;
+        map.serialize_entry("start", &request.start.to_rfc3339())?;
+        map.serialize_entry("end", &request.end.to_rfc3339())?;
+        map.serialize_entry("end_inferred", &request.end_inferred)?;
+        map.serialize_entry("close_code",
+                            &request.close.as_ref().map(|close| close.code))?;
+        map.serialize_entry("close_reason",
+                            &request.close.as_ref().map(|close| &close.reason))?;
+        map.serialize_entry("activity", &activity)?;
+        map.end()
+    }
+}
+
+
+/**
+ * Pairs each finished request up with its activity, ordered by when the
+ * requests started.
+ */
+pub fn collect_request_records(requests: RequestCollector,
+                               activity: ActivityCollector)
+        -> Vec<RequestRecord> {
+    let mut times = activity.into_times();
+    let mut records: Vec<RequestRecord> = requests.into_requests()
+        .map(|request| RequestRecord {
+            activity: times.remove(&request.request_id).unwrap_or_default(),
+            request,
+        })
+        .collect();
+    records.sort_by_key(|record| (record.request.start, record.request.request_id));
+    records
+}
+
+
+/**
+ * The ways records can be written out.
+ */
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum Format {
+    // One JSON object per line
+    Jsonl,
+
+    // With a header row. A request's activity is all in one column,
+    // separated by spaces.
+    Csv,
+
+    // What --dataset reads. Not meant to be read by anything else.
+    Dataset,
+}
+
+
+impl Format {
+    pub const ALL: [Format; 3] = [Format::Jsonl, Format::Csv, Format::Dataset];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Format::Jsonl => "jsonl",
+            Format::Csv => "csv",
+            Format::Dataset => "dataset",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Format> {
+        Format::ALL.iter().copied().find(|format| format.name() == name)
+    }
+}
+
+
+const CSV_HEADER: &str =
+    "request_id,game_id,user_id,is_admin,version,last_seen_index,host,start,\
+     end,end_inferred,close_code,close_reason,activity";
+
+
+fn or_empty(value: Option<impl ToString>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+
+// Close reasons are whatever the client sent, so they can have anything in
+// them
+fn csv_quote(value: &str) -> String {
+    if value.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+
+pub fn write_records(records: &[RequestRecord], format: Format,
+                     mut writer: impl Write) -> io::Result<()> {
+    match format {
+        Format::Dataset => return write_dataset(records, writer),
+        Format::Csv => writeln!(writer, "{}", CSV_HEADER)?,
+        Format::Jsonl => (),
+    }
+
+    for record in records {
+        match format {
+            Format::Jsonl => serde_json::ser::to_writer(&mut writer, record)?,
+            Format::Csv => {
+                // Other than the close reason, none of these can ever have
+                // a comma or quote in them, so nothing else needs escaping.
+                // Query parameters the client didn't send (and close codes
+                // that weren't logged) are left empty.
+                let request = &record.request;
+                let activity: Vec<String> =
+                    record.activity.iter().map(|at| at.to_rfc3339()).collect();
+                let close = request.close.as_ref();
+                write!(writer, "{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                       request.request_id, request.game_id,
+                       request.user_id, request.is_admin,
+                       or_empty(request.query.version),
+                       or_empty(request.query.last_seen_index),
+                       request.host,
+                       request.start.to_rfc3339(), request.end.to_rfc3339(),
+                       request.end_inferred,
+                       or_empty(close.map(|close| close.code)),
+                       csv_quote(&or_empty(close.map(|close| &close.reason))),
+                       activity.join(" "))?;
+            },
+            Format::Dataset => unreachable!(),
+        }
+        writeln!(writer)?;
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use shmeppy_log::lex::locate_parts;
+    use crate::activity::ActivityCollector;
+    use crate::extract::{
+        Format, collect_request_records, csv_quote, write_records};
+    use crate::request::RequestCollector;
+
+    const LOG: &[&[u8]] = &[
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/381468491?version=2&lastSeenIndex=328 1.1"#,
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:35:08 AM] Successfully authenticated as: { accountId: 'c7d585cd803aafa5', displayName: 'John' }"#,
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:35:08 AM] Client added to client DB: {"gameId":381468491,"clientId":18378,"account":{"accountId":"c7d585cd803aafa5","displayName":"John"},"isAdmin":true,"initialLastSeenIndex":328,"lastPing":1590456908483}"#,
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:36:00 AM] Committed 1 operation(s)."#,
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:35:30 AM] Committed 1 operation(s)."#,
+        br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:36:50 AM] Finished websocket: 1005 ''"#,
+        // Never finishes, and its end isn't inferred, so it's left out
+        br#"shmeppy-1 shmeppy-app: (457edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:34:47 AM] Started websocket: GET /game-socket/381468491?version=2&lastSeenIndex=328 1.1"#,
+    ];
+
+    fn written(format: Format) -> String {
+        let mut requests = RequestCollector::new();
+        let mut activity = ActivityCollector::new();
+        for line in LOG {
+            let parts = locate_parts(line).unwrap();
+            requests.update(&parts).unwrap();
+            activity.update(&parts).unwrap();
+        }
+
+        let records = collect_request_records(requests, activity);
+        let mut written = Vec::new();
+        write_records(&records, format, &mut written).unwrap();
+        String::from_utf8(written).unwrap()
+    }
+
+    #[test]
+    fn jsonl() {
+        assert_eq!(written(Format::Jsonl), concat!(
+            r#"{"request_id":"357edc79-03fe-4787-910a-d2f14302348c","#,
+            r#""game_id":381468491,"user_id":"account_id:14399562501253148581","#,
+            r#""is_admin":true,"version":2,"last_seen_index":328,"#,
+            r#""host":"shmeppy-1","start":"2020-05-26T01:33:47+00:00","#,
+            r#""end":"2020-05-26T01:36:50+00:00","end_inferred":false,"#,
+            r#""close_code":1005,"close_reason":"","#,
+            r#""activity":["#,
+            r#""2020-05-26T01:35:30+00:00","2020-05-26T01:36:00+00:00"]}"#,
+            "\n"));
+    }
+
+    #[test]
+    fn csv() {
+        assert_eq!(written(Format::Csv), concat!(
+            "request_id,game_id,user_id,is_admin,version,last_seen_index,",
+            "host,start,end,end_inferred,close_code,close_reason,activity\n",
+            "357edc79-03fe-4787-910a-d2f14302348c,381468491,",
+            "account_id:14399562501253148581,true,2,328,shmeppy-1,",
+            "2020-05-26T01:33:47+00:00,",
+            "2020-05-26T01:36:50+00:00,false,1005,,",
+            "2020-05-26T01:35:30+00:00 2020-05-26T01:36:00+00:00\n"));
+
+        assert_eq!(csv_quote("normal"), "normal");
+        assert_eq!(csv_quote(r#"said "bye", left"#), r#""said ""bye"", left""#);
+    }
+}
@@ -0,0 +1,54 @@
+use std::io::{BufWriter, Write, stdout};
+use std::process::exit;
+
+use fast_convoker::analysis::{Analysis, finish_run, read_logs};
+use fast_convoker::extract::{Format, collect_request_records, write_records};
+use fast_convoker::options::{Options, USAGE};
+
+
+const USAGE_MESSAGE: &str = "usage: extract-requests [OPTIONS] {jsonl|csv|dataset} [LOGS...]";
+
+
+fn main() {
+    let options = Options::from_env(USAGE_MESSAGE);
+    let mut args = options.positional.iter().cloned();
+    let format = match args.next().as_deref().and_then(Format::from_name) {
+        Some(format) => format,
+        None => {
+            eprintln!("{}\n{}", USAGE_MESSAGE, USAGE);
+            exit(1);
+        },
+    };
+
+    // Convocations are only asked for to get the requests collected, and
+    // never actually worked out.
+    let result = read_logs(&[Analysis::Convocations], args, &options)
+        .and_then(|mut pipeline| {
+            let (mut requests, activity) = pipeline.take_requests()
+                .expect("convocations always collect requests");
+
+            // Ends are inferred the same way they would be in
+            // shmeppy-metrics, and whatever's still open is left out
+            let inferred = requests.infer_ends(
+                &activity.last_active(), pipeline.quiet_before(None));
+            let open = requests.open_requests().len();
+            finish_run(pipeline, &options)?;
+            if inferred.total() > 0 {
+                eprintln!("{}", inferred);
+            }
+            if open > 0 {
+                eprintln!("{} game request(s) were still open at the end of \
+                           the logs, and were left out", open);
+            }
+
+            let records = collect_request_records(requests, activity);
+            let mut writer = BufWriter::new(stdout().lock());
+            write_records(&records, format, &mut writer)?;
+            Ok(writer.flush()?)
+        });
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        exit(1);
+    }
+}
@@ -0,0 +1,235 @@
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+
+use shmeppy_log::error::Error;
+use shmeppy_log::feed::Feed;
+use shmeppy_log::input::{Source, collect_sources};
+use shmeppy_log::lex::Lexer;
+
+use crate::analysis::{Analysis, Pipeline, Report, feed_in_parallel};
+use crate::options::Options;
+
+
+// How long to wait for more lines once we've caught up
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+
+/**
+ * Reads a log file as it's written, following it when it's rotated.
+ *
+ * We notice a rotation by the file at path being replaced with a new one, or
+ * by it being truncated out from under us (logrotate's copytruncate). Either
+ * way we finish off what's left of the old file first, so nothing's lost.
+ */
+pub struct Follower {
+    path: PathBuf,
+    reader: BufReader<File>,
+    inode: u64,
+
+    // How far into the file we've read, and how many lines that was
+    position: u64,
+    line_number: usize,
+
+    // The start of a line that's still being written
+    partial: Vec<u8>,
+}
+
+
+impl Follower {
+    pub fn open(path: &Path) -> io::Result<Follower> {
+        let file = File::open(path)?;
+        Ok(Follower {
+            path: path.to_path_buf(),
+            inode: file.metadata()?.ino(),
+            reader: BufReader::new(file),
+            position: 0,
+            line_number: 0,
+            partial: Vec::new(),
+        })
+    }
+
+    /**
+     * Calls handle with each whole line (and its line number) written since
+     * the last poll.
+     */
+    pub fn poll<E: From<io::Error>>(
+            &mut self, mut handle: impl FnMut(usize, Vec<u8>) -> Result<(), E>)
+            -> Result<(), E> {
+        loop {
+            loop {
+                let read = self.reader.read_until(b'\n', &mut self.partial)?;
+                if read == 0 {
+                    break;
+                }
+
+                self.position += read as u64;
+                if self.partial.ends_with(b"\n") {
+                    let mut line = std::mem::take(&mut self.partial);
+                    line.pop();
+                    self.line_number += 1;
+                    handle(self.line_number, line)?;
+                }
+            }
+
+            match self.rotated()? {
+                Some(next) => {
+                    // The old file's never getting the rest of this line
+                    if !self.partial.is_empty() {
+                        let line = std::mem::take(&mut self.partial);
+                        handle(self.line_number + 1, line)?;
+                    }
+                    *self = next;
+                },
+                None => return Ok(()),
+            }
+        }
+    }
+
+    // Gives back the file that's replaced ours, if it has been
+    fn rotated(&self) -> io::Result<Option<Follower>> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+
+            // Partway through a rotation there can be nothing there at all
+            Err(error) if error.kind() == io::ErrorKind::NotFound =>
+                return Ok(None),
+            Err(error) => return Err(error),
+        };
+
+        if metadata.ino() == self.inode && metadata.len() >= self.position {
+            Ok(None)
+        } else {
+            Follower::open(&self.path).map(Some)
+        }
+    }
+}
+
+
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+
+/**
+ * Reads the logs at paths, and then keeps reading the live log at path
+ * forever, handing write a fresh report every options.interval.
+ *
+ * This only gives back if something goes wrong.
+ */
+pub fn follow(analyses: &[Analysis], paths: &[String], path: &Path,
+              options: &Options,
+              mut write: impl FnMut(&Report) -> Result<(), Error>)
+              -> Result<(), Error> {
+    // With nothing else given we'd otherwise end up waiting on stdin
+    let mut pipeline = if paths.is_empty() {
+        Pipeline::new(analyses, options)
+    } else {
+        // The live log's read below, and shouldn't be counted twice
+        let sources: Vec<Source> = collect_sources(paths.iter().cloned())?
+            .into_iter()
+            .filter(|source| match source {
+                Source::File(other) => !is_same_file(other, path),
+                Source::Stdin => true,
+            })
+            .collect();
+        feed_in_parallel(analyses, &sources, options)?
+    };
+
+    let source = Source::File(path.to_path_buf());
+    let mut follower = Follower::open(path).map_err(|error| Error::Read {
+        source: source.clone(),
+        error,
+    })?;
+    let mut lexer = Lexer::new();
+    let mut last_written: Option<Instant> = None;
+    loop {
+        follower.poll(|line_number, line| {
+            pipeline.feed_line(&source, &mut lexer, line_number, line)
+        })?;
+
+        if last_written.is_none_or(|at| at.elapsed() >= options.interval) {
+            write(&pipeline.snapshot(Utc::now()))?;
+            last_written = Some(Instant::now());
+        }
+
+        sleep(POLL_INTERVAL);
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::{self, Write};
+    use std::path::PathBuf;
+
+    use crate::follow::Follower;
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "fast-convoker-follow-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn poll(follower: &mut Follower) -> Vec<(usize, String)> {
+        let mut lines = Vec::new();
+        follower.poll(|line_number, line| -> io::Result<()> {
+            lines.push((line_number, String::from_utf8(line).unwrap()));
+            Ok(())
+        }).unwrap();
+        lines
+    }
+
+    fn append(path: &PathBuf, contents: &str) {
+        OpenOptions::new().append(true).create(true).open(path).unwrap()
+            .write_all(contents.as_bytes()).unwrap();
+    }
+
+    #[test]
+    fn across_rotations() {
+        let dir = scratch_dir("rotations");
+        let path = dir.join("app.log");
+        append(&path, "one\ntw");
+
+        let mut follower = Follower::open(&path).unwrap();
+        assert_eq!(poll(&mut follower), vec![(1, "one".to_string())]);
+
+        // The rest of a half-written line shows up
+        append(&path, "o\nthree\n");
+        assert_eq!(poll(&mut follower),
+                   vec![(2, "two".to_string()), (3, "three".to_string())]);
+        assert_eq!(poll(&mut follower), vec![]);
+
+        // Moved out of the way, with a bit more written to it first
+        append(&path, "four\n");
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        append(&dir.join("app.log.1"), "five\n");
+        append(&path, "new one\n");
+        assert_eq!(poll(&mut follower), vec![
+            (4, "four".to_string()),
+            (5, "five".to_string()),
+            (1, "new one".to_string()),
+        ]);
+
+        // Copied and truncated in place. There's no telling this apart from
+        // a rotation if the file's grown past where we were before we look,
+        // so look right away.
+        fs::write(&path, "").unwrap();
+        assert_eq!(poll(&mut follower), vec![]);
+        append(&path, "truncated\n");
+        assert_eq!(poll(&mut follower), vec![(1, "truncated".to_string())]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,7 +3,9 @@ use std::collections::BTreeMap;
 
 use spet::vecspet::VecSpet;
 
-use crate::request::{Request, UserId, GameId};
+use shmeppy_log::ids::{GameId, UserId};
+
+use crate::request::Request;
 use crate::accumulator::push_onto_accumulator;
 use crate::timespan::TimeSpan;
 
@@ -17,7 +19,7 @@ pub struct GlobalPresence {
 
 pub fn collect_global_presences(
         requests: impl Iterator<Item=Request>,
-        activity: BTreeMap<GameId, VecSpet<TimeSpan>>)
+        activity: &BTreeMap<GameId, VecSpet<TimeSpan>>)
         -> Vec<GlobalPresence> {
     // Group each of a user's requests together
     let mut groups: BTreeMap<UserId, Vec<Request>> = BTreeMap::new();
@@ -42,16 +44,18 @@ pub fn collect_global_presences(
 }
 
 
+#[cfg(test)]
 mod test {
     use std::collections::BTreeMap;
 
     use chrono::{Utc, TimeZone};
     use spet::vecspet::VecSpet;
 
+    use shmeppy_log::ids::{GameId, UserId};
+    use shmeppy_log::parse::UUID;
     use crate::globalpresence::GlobalPresence;
     use crate::globalpresence::collect_global_presences;
-    use crate::request::{Request, UserId, GameId};
-    use crate::parse::UUID;
+    use crate::request::{Request, SocketQuery};
     use crate::timespan::TimeSpan;
 
     #[test]
@@ -64,6 +68,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
             Request {
                 request_id: UUID(2),
@@ -72,6 +80,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
         ];
 
@@ -83,7 +95,7 @@ mod test {
         ]));
 
 
-        let presences = collect_global_presences(requests.into_iter(), activity);
+        let presences = collect_global_presences(requests.into_iter(), &activity);
         assert_eq!(presences.len(), 1);
 
         use spet::span::CreatableSpan;
@@ -112,6 +124,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
             Request {
                 request_id: UUID(2),
@@ -120,6 +136,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(4),
                 is_admin: false,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
         ];
 
@@ -131,7 +151,7 @@ mod test {
         ]));
 
         let presences = collect_global_presences(requests.into_iter(),
-                                                 activity);
+                                                 &activity);
 
         use spet::span::CreatableSpan;
         assert_eq!(presences, vec![
@@ -0,0 +1,25 @@
+pub mod request;
+pub mod activity;
+pub mod presence;
+pub mod globalpresence;
+pub mod accumulator;
+pub mod timespan;
+pub mod convocation;
+pub mod reconnect;
+pub mod protocolversion;
+pub mod closecode;
+pub mod activeuser;
+pub mod compact;
+pub mod endpoint;
+pub mod analysis;
+pub mod options;
+pub mod websocket;
+pub mod diagnostics;
+pub mod output;
+pub mod checkpoint;
+pub mod follow;
+pub mod shard;
+pub mod extract;
+pub mod dataset;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
@@ -0,0 +1,89 @@
+use std::io::stdout;
+use std::path::Path;
+use std::process::exit;
+
+use fast_convoker::analysis::{Analysis, Report, run};
+use fast_convoker::follow::follow;
+use shmeppy_log::error::Error;
+use fast_convoker::options::{Options, USAGE};
+use fast_convoker::output::write_atomically;
+use fast_convoker::shard::write_shards;
+
+
+fn usage_message() -> String {
+    let names: Vec<&str> =
+        Analysis::ALL.iter().map(|analysis| analysis.name()).collect();
+    format!(
+        "usage: shmeppy-metrics [OPTIONS] {{{}}} [LOGS...]\n       \
+         shmeppy-metrics [OPTIONS] all WRITE_INTO [LOGS...]",
+        names.join("|"))
+}
+
+
+fn usage() -> ! {
+    eprintln!("{}\n{}", usage_message(), USAGE);
+    exit(1);
+}
+
+
+// Writes every analysis in report out as its own file (or files) in dir
+fn write_files(dir: &Path, analyses: &[Analysis], report: &Report,
+               options: &Options) -> Result<(), Error> {
+    if options.shard_by_month {
+        return write_shards(dir, analyses, report, options);
+    }
+
+    for &analysis in analyses {
+        // Nobody watching the output directory should ever see a
+        // half-written file.
+        write_atomically(&dir.join(analysis.file_name()),
+                         |writer| report.write_for(analysis, options, writer))?;
+    }
+
+    Ok(())
+}
+
+
+fn main() {
+    let options = Options::from_env(&usage_message());
+    let mut args = options.positional.iter().cloned();
+    let subcommand = args.next().unwrap_or_else(|| usage());
+
+    let (analyses, write_into) = if subcommand == "all" {
+        let dir = args.next().unwrap_or_else(|| usage());
+        (Analysis::ALL.to_vec(), Some(dir))
+    } else {
+        match Analysis::from_name(&subcommand) {
+            Some(analysis) => (vec![analysis], None),
+            None => usage(),
+        }
+    };
+
+    let result = match (&options.follow, &write_into) {
+        (Some(path), Some(dir)) => {
+            let paths: Vec<String> = args.collect();
+            follow(&analyses, &paths, path, &options, |report| {
+                #[cfg(feature = "sqlite")]
+                if let Some(path) = &options.sqlite {
+                    fast_convoker::sqlite::write_sqlite(report, path)?;
+                }
+
+                write_files(Path::new(dir), &analyses, report, &options)
+            })
+        },
+        // Writing reports to stdout over and over wouldn't be much use
+        (Some(_), None) => usage(),
+        (None, _) => run(&analyses, args, &options).and_then(|report| {
+            match &write_into {
+                Some(dir) =>
+                    write_files(Path::new(dir), &analyses, &report, &options),
+                None => Ok(report.write_for(analyses[0], &options, stdout())?),
+            }
+        }),
+    };
+
+    if let Err(error) = result {
+        eprintln!("error: {}", error);
+        exit(1);
+    }
+}
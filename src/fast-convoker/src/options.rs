@@ -0,0 +1,381 @@
+use std::path::PathBuf;
+use std::process::exit;
+use std::thread;
+use std::time::Duration;
+
+use shmeppy_log::daterange::parse_date;
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::filter::Filter;
+use shmeppy_log::zones::{Zones, parse_zone};
+
+use crate::convocation::ConvocationRules;
+
+
+/**
+ * The command line options shared by every binary.
+ *
+ * Flags can go anywhere. Everything that isn't a flag is left in
+ * `positional`, in order, for the binary to make sense of.
+ */
+#[derive(Debug)]
+pub struct Options {
+    pub filter: Filter,
+
+    // Where to write the rejected-line diagnostics, if anywhere
+    pub diagnostics: Option<PathBuf>,
+
+    // Stop at the first bad line or unreadable file, rather than skipping
+    // it and carrying on
+    pub strict: bool,
+
+    pub zones: Zones,
+
+    // How many sources to read at once
+    pub jobs: usize,
+
+    // Where to keep what we learned from each log file, so the next run
+    // only has to read the new ones
+    pub checkpoints: Option<PathBuf>,
+
+    // The live log to keep reading once everything else has been, and how
+    // often to write out what we've got while doing so
+    pub follow: Option<PathBuf>,
+    pub interval: Duration,
+
+    // A dataset made by extract-requests to read instead of any logs
+    pub dataset: Option<PathBuf>,
+
+    // A SQLite database to write everything into, as well as the usual
+    // output. Always None unless built with the sqlite feature.
+    pub sqlite: Option<PathBuf>,
+
+    // Write each analysis as one file per month, plus a manifest, rather
+    // than as one file each
+    pub shard_by_month: bool,
+
+    // Write active users with each user ID listed once, rather than once
+    // for every day they were active
+    pub compact_active_users: bool,
+
+    // The day to treat as today, which is as far as active users goes
+    pub as_of: Option<DateString>,
+
+    // How long a request that never finished has to have been quiet before
+    // we give up on it and take it to have ended at its last line. Without
+    // this, only a server restart ends them.
+    pub open_timeout: Option<Duration>,
+
+    // What it takes to count as a convocation. These only come into it once
+    // the requests have been read, so they can be tried out against a
+    // --dataset without going back to the logs.
+    pub convocation_rules: ConvocationRules,
+
+    pub positional: Vec<String>,
+}
+
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            filter: Filter::default(),
+            diagnostics: None,
+            strict: false,
+            zones: Zones::default(),
+            jobs: thread::available_parallelism().map_or(1, usize::from),
+            checkpoints: None,
+            follow: None,
+            interval: Duration::from_secs(60),
+            dataset: None,
+            sqlite: None,
+            shard_by_month: false,
+            compact_active_users: false,
+            as_of: None,
+            open_timeout: None,
+            convocation_rules: ConvocationRules::default(),
+            positional: Vec::new(),
+        }
+    }
+}
+
+
+pub const USAGE: &str = "\
+options:
+  --min-level LEVEL  ignore lines below LEVEL (ie: INFO to drop DEBUG)
+  --host HOST        only look at lines from HOST (can be repeated)
+  --since DATE       only look at lines from DATE (YYYY-MM-DD, in the
+                     logs' timezone) on. Requests that started earlier are
+                     left out.
+  --until DATE       only look at lines up through DATE. Requests still
+                     going at the end of it are cut off there, and their
+                     convocations marked provisional.
+  --as-of DATE       report as if it were DATE, which is the last day active
+                     users goes up to (default the day of the last line
+                     read, so the same logs always give the same output)
+  --open-timeout MINUTES
+                     take game requests that never finished and haven't
+                     logged anything for MINUTES before the last line read
+                     to have ended at their last line (by default they're
+                     only ended by a server restart, and are otherwise
+                     reported as still open)
+  --min-people N     how many people have to be in a game at once for it
+                     to be a convocation (default 3)
+  --max-gap MINUTES  close over gaps in a convocation shorter than MINUTES
+                     (default 5)
+  --min-length MINUTES
+                     only keep convocations longer than MINUTES (default
+                     40)
+  --max-break MINUTES
+                     merge convocations less than MINUTES apart (default
+                     90)
+  --diagnostics PATH write counts and samples of rejected lines to PATH
+  --strict           stop at the first bad line or unreadable file
+  --lenient          skip bad lines and unreadable files (the default)
+  --log-tz ZONE      the timezone the logs were written in (default UTC)
+  --report-tz ZONE   the timezone to bucket days by (default UTC)
+  --jobs N           read N log files at once (default one per core)
+  --checkpoints DIR  save what was read from each log file in DIR, and
+                     skip reading files that haven't changed since
+  --follow PATH      after reading the logs, keep reading the live log at
+                     PATH as it's written and rotated (only with all)
+  --interval SECONDS how often --follow rewrites the files (default 60)
+  --dataset PATH     read the requests made by `extract-requests dataset`
+                     from PATH instead of reading logs (only for
+                     analyses built from requests, ie: not
+                     registrations, event counts or websockets)
+  --sqlite PATH      also write everything into the SQLite database at
+                     PATH (needs the sqlite feature)
+  --shard-by-month   with all, write one file per month of each analysis
+                     and a manifest.json listing them
+  --compact-active-users
+                     write active users as a list of users plus
+                     [user index, seconds] pairs for each day";
+
+
+fn parse_jobs(raw: &str) -> Result<usize, String> {
+    match raw.parse() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(format!("--jobs needs a positive number, not {}", raw)),
+    }
+}
+
+
+fn parse_interval(raw: &str) -> Result<Duration, String> {
+    match raw.parse() {
+        Ok(seconds) if seconds > 0 => Ok(Duration::from_secs(seconds)),
+        _ => Err(format!("--interval needs a positive number, not {}", raw)),
+    }
+}
+
+
+fn parse_open_timeout(raw: &str) -> Result<Duration, String> {
+    match raw.parse::<u64>() {
+        Ok(minutes) if minutes > 0 => Ok(Duration::from_secs(minutes * 60)),
+        _ => Err(format!("--open-timeout needs a positive number, not {}",
+                         raw)),
+    }
+}
+
+
+fn parse_min_people(raw: &str) -> Result<usize, String> {
+    match raw.parse() {
+        Ok(people) if people > 0 => Ok(people),
+        _ => Err(format!("--min-people needs a positive number, not {}", raw)),
+    }
+}
+
+
+fn parse_minutes(flag: &str, raw: &str) -> Result<chrono::Duration, String> {
+    match raw.parse::<u32>() {
+        Ok(minutes) => Ok(chrono::Duration::minutes(minutes.into())),
+        _ => Err(format!("{} needs a number of minutes, not {}", flag, raw)),
+    }
+}
+
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>)
+            -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if options.filter.parse_flag(&arg, &mut args)? {
+                continue;
+            }
+
+            let mut value = |flag: &str| args.next().ok_or_else(
+                || format!("{} needs a value", flag));
+
+            match arg.as_str() {
+                "--as-of" => options.as_of = Some(parse_date(&value(&arg)?)?),
+                "--open-timeout" =>
+                    options.open_timeout =
+                        Some(parse_open_timeout(&value(&arg)?)?),
+                "--min-people" =>
+                    options.convocation_rules.min_people =
+                        parse_min_people(&value(&arg)?)?,
+                "--max-gap" =>
+                    options.convocation_rules.max_gap =
+                        parse_minutes(&arg, &value(&arg)?)?,
+                "--min-length" =>
+                    options.convocation_rules.min_length =
+                        parse_minutes(&arg, &value(&arg)?)?,
+                "--max-break" =>
+                    options.convocation_rules.max_break =
+                        parse_minutes(&arg, &value(&arg)?)?,
+                "--diagnostics" =>
+                    options.diagnostics = Some(value(&arg)?.into()),
+                "--strict" => options.strict = true,
+                "--lenient" => options.strict = false,
+                "--log-tz" =>
+                    options.zones.log = parse_zone(&value(&arg)?)?,
+                "--report-tz" =>
+                    options.zones.report = parse_zone(&value(&arg)?)?,
+                "--jobs" => options.jobs = parse_jobs(&value(&arg)?)?,
+                "--checkpoints" =>
+                    options.checkpoints = Some(value(&arg)?.into()),
+                "--follow" => options.follow = Some(value(&arg)?.into()),
+                "--interval" =>
+                    options.interval = parse_interval(&value(&arg)?)?,
+                "--dataset" => options.dataset = Some(value(&arg)?.into()),
+                "--shard-by-month" => options.shard_by_month = true,
+                "--compact-active-users" =>
+                    options.compact_active_users = true,
+                "--sqlite" if cfg!(feature = "sqlite") =>
+                    options.sqlite = Some(value(&arg)?.into()),
+                "--sqlite" =>
+                    return Err("--sqlite needs building with the sqlite \
+                                feature".to_string()),
+                _ if arg.starts_with("--") =>
+                    return Err(format!("unknown option {}", arg)),
+                _ => options.positional.push(arg),
+            }
+        }
+
+        options.filter.check()?;
+        Ok(options)
+    }
+
+    /**
+     * Parses this process's arguments, exiting with the usage message if
+     * they don't make sense.
+     */
+    pub fn from_env(usage: &str) -> Options {
+        match Options::parse(std::env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => {
+                eprintln!("{}\n{}\n{}", message, usage, USAGE);
+                exit(1);
+            },
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use shmeppy_log::datestring::DateString;
+    use shmeppy_log::lex::Level;
+    use crate::convocation::ConvocationRules;
+    use crate::options::Options;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn flags_anywhere() {
+        let options = parse(&[
+            "all", "--min-level", "info", "out", "--host", "shmeppy-1",
+            "logs"]).unwrap();
+        assert_eq!(options.positional, vec!["all", "out", "logs"]);
+        assert_eq!(options.filter.min_level, Some(Level::Info));
+        assert_eq!(options.filter.hosts, vec![b"shmeppy-1".to_vec()]);
+        assert!(!options.strict);
+    }
+
+    #[test]
+    fn zones() {
+        let options = parse(&["--report-tz", "America/Los_Angeles"]).unwrap();
+        assert_eq!(options.zones.log, chrono_tz::Tz::UTC);
+        assert_eq!(options.zones.report, chrono_tz::America::Los_Angeles);
+        assert!(parse(&["--log-tz", "Mars/Olympus_Mons"]).is_err());
+    }
+
+    #[test]
+    fn last_strictness_wins() {
+        assert!(parse(&["--lenient", "--strict"]).unwrap().strict);
+        assert!(!parse(&["--strict", "--lenient"]).unwrap().strict);
+    }
+
+    #[test]
+    fn jobs() {
+        assert!(parse(&[]).unwrap().jobs >= 1);
+        assert_eq!(parse(&["--jobs", "3"]).unwrap().jobs, 3);
+        assert!(parse(&["--jobs", "0"]).is_err());
+        assert!(parse(&["--jobs", "lots"]).is_err());
+    }
+
+    #[test]
+    fn follow() {
+        let options = parse(&[
+            "all", "out", "--follow", "logs/app.log", "--interval", "5"])
+            .unwrap();
+        assert_eq!(options.follow, Some("logs/app.log".into()));
+        assert_eq!(options.interval, std::time::Duration::from_secs(5));
+        assert!(parse(&["--interval", "0"]).is_err());
+    }
+
+    #[test]
+    fn open_timeout() {
+        assert_eq!(parse(&[]).unwrap().open_timeout, None);
+        assert_eq!(parse(&["--open-timeout", "90"]).unwrap().open_timeout,
+                   Some(std::time::Duration::from_secs(90 * 60)));
+        assert!(parse(&["--open-timeout", "0"]).is_err());
+    }
+
+    #[test]
+    fn convocation_rules() {
+        let options = parse(&[
+            "--min-people", "2", "--max-gap", "0", "--min-length", "30",
+            "--max-break", "120"]).unwrap();
+        assert_eq!(options.convocation_rules, ConvocationRules {
+            min_people: 2,
+            max_gap: Duration::zero(),
+            min_length: Duration::minutes(30),
+            max_break: Duration::minutes(120),
+        });
+        assert_eq!(parse(&[]).unwrap().convocation_rules,
+                   ConvocationRules::default());
+        assert!(parse(&["--min-people", "0"]).is_err());
+        assert!(parse(&["--max-break", "-5"]).is_err());
+    }
+
+    #[test]
+    fn dates() {
+        let options = parse(&["--since", "2020-05-01", "--until", "2020-5-31"])
+            .unwrap();
+        assert_eq!(options.filter.dates.since.unwrap().to_string(), "2020-05-01");
+        assert_eq!(options.filter.dates.until.unwrap().to_string(), "2020-05-31");
+        assert!(parse(&["--since", "last week"]).is_err());
+        assert!(parse(&["--since", "2020-06-01", "--until", "2020-05-31"]).is_err());
+        assert_eq!(parse(&["--as-of", "2020-6-1"]).unwrap().as_of,
+                   Some(DateString(*b"2020-06-01")));
+    }
+
+    #[test]
+    fn output_shape() {
+        let options = parse(&["all", "out", "--shard-by-month",
+                              "--compact-active-users"]).unwrap();
+        assert!(options.shard_by_month && options.compact_active_users);
+        assert!(!parse(&["all", "out"]).unwrap().compact_active_users);
+    }
+
+    #[test]
+    fn bad_flags() {
+        assert!(parse(&["--min-level"]).is_err());
+        assert!(parse(&["--min-level", "loud"]).is_err());
+        assert!(parse(&["--nope"]).is_err());
+    }
+}
@@ -0,0 +1,50 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+
+/**
+ * Writes to a file next to destination and then renames it over
+ * destination, so nobody reading destination ever sees it half-written.
+ */
+pub fn write_atomically<E: Into<io::Error>>(
+        destination: &Path,
+        write: impl FnOnce(&mut dyn Write) -> Result<(), E>)
+        -> io::Result<()> {
+    let file_name = destination.file_name().ok_or_else(
+        || io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(".tmp");
+    let temp = destination.with_file_name(temp_name);
+
+    let mut writer = BufWriter::new(File::create(&temp)?);
+    write(&mut writer).map_err(Into::into)?;
+    writer.flush()?;
+    drop(writer);
+
+    fs::rename(&temp, destination)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::output::write_atomically;
+
+    #[test]
+    fn replaces_destination() {
+        let dir = std::env::temp_dir().join(format!(
+            "shmeppy-output-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let destination = dir.join("thing.json");
+        std::fs::write(&destination, "old").unwrap();
+
+        write_atomically(&destination, |writer| {
+            serde_json::to_writer(writer, &[1, 2])
+        }).unwrap();
+
+        assert_eq!(std::fs::read_to_string(&destination).unwrap(), "[1,2]");
+        assert!(!dir.join(".thing.json.tmp").exists());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
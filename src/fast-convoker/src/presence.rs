@@ -2,7 +2,9 @@ use std::collections::BTreeMap;
 
 use spet::vecspet::VecSpet;
 
-use crate::request::{Request, GameId, UserId};
+use shmeppy_log::ids::{GameId, UserId};
+
+use crate::request::Request;
 use crate::accumulator::push_onto_accumulator;
 use crate::timespan::TimeSpan;
 
@@ -43,7 +45,7 @@ pub fn collect_presences(requests: impl Iterator<Item=Request>)
             spans.sort_unstable();
 
             Presence {
-                spet: VecSpet::from_sorted_iter(spans.into_iter()),
+                spet: VecSpet::from_sorted_iter(spans),
                 game_id,
                 user_id,
                 is_admin
@@ -61,14 +63,16 @@ pub fn collect_presences(requests: impl Iterator<Item=Request>)
 }
 
 
+#[cfg(test)]
 mod test {
     use chrono::{Utc, TimeZone};
     use spet::vecspet::VecSpet;
 
+    use shmeppy_log::ids::UserId;
+    use shmeppy_log::parse::UUID;
     use crate::presence::Presence;
     use crate::presence::collect_presences;
-    use crate::request::{Request, UserId};
-    use crate::parse::UUID;
+    use crate::request::{Request, SocketQuery};
     use crate::timespan::TimeSpan;
 
     #[test]
@@ -81,6 +85,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
             Request {
                 request_id: UUID(2),
@@ -89,6 +97,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
         ];
 
@@ -124,6 +136,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: true,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
             Request {
                 request_id: UUID(2),
@@ -132,6 +148,10 @@ mod test {
                 game_id: 2,
                 user_id: UserId::AccountId(3),
                 is_admin: false,
+                query: SocketQuery::default(),
+                host: "shmeppy-1".to_string(),
+                close: None,
+                end_inferred: false,
             },
         ];
 
@@ -0,0 +1,113 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono_tz::Tz;
+use serde::Serialize;
+
+use shmeppy_log::ids::UserId;
+
+use crate::request::Request;
+
+
+/**
+ * How many people connected with one protocol version on one day.
+ */
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct VersionCounts {
+    pub users: usize,
+
+    // Every game websocket, so someone who reconnects counts more than once
+    pub sessions: usize,
+}
+
+
+// What requests that didn't say which version they are go under. Clients
+// from before the version was sent are probably the oldest of all.
+const UNKNOWN_VERSION: &str = "unknown";
+
+
+/**
+ * Counts each day's users and sessions by the protocol version their client
+ * connected with.
+ *
+ * The result looks like {day: {version: {"users": ..., "sessions": ...}}},
+ * where requests are counted on the day in report_tz that they started.
+ * Anonymous users can't be told apart, so they only count as sessions.
+ */
+pub fn collect_protocol_versions(requests: &[Request], report_tz: Tz)
+        -> BTreeMap<String, BTreeMap<String, VersionCounts>> {
+    // The users and sessions of each version on each day
+    let mut seen: BTreeMap<String, BTreeMap<String, (BTreeSet<UserId>, usize)>> =
+        BTreeMap::new();
+    for request in requests {
+        let day = request.start.with_timezone(&report_tz)
+            .format("%Y-%m-%d").to_string();
+        let version = match request.query.version {
+            Some(version) => version.to_string(),
+            None => UNKNOWN_VERSION.to_string(),
+        };
+
+        let (users, sessions) =
+            seen.entry(day).or_default().entry(version).or_default();
+        *sessions += 1;
+        if request.user_id != UserId::Anonymous {
+            users.insert(request.user_id);
+        }
+    }
+
+    seen.into_iter()
+        .map(|(day, versions)| {
+            let versions = versions.into_iter()
+                .map(|(version, (users, sessions))| {
+                    (version, VersionCounts { users: users.len(), sessions })
+                })
+                .collect();
+            (day, versions)
+        })
+        .collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use shmeppy_log::ids::UserId;
+    use shmeppy_log::parse::UUID;
+    use crate::protocolversion::collect_protocol_versions;
+    use crate::request::{Request, SocketQuery};
+
+    fn request(user_id: UserId, hour: u32, version: Option<u32>) -> Request {
+        Request {
+            request_id: UUID(0),
+            start: Utc.ymd(2020, 5, 26).and_hms(hour, 0, 0),
+            end: Utc.ymd(2020, 5, 26).and_hms(hour, 30, 0),
+            game_id: 5,
+            user_id,
+            is_admin: false,
+            query: SocketQuery { version, last_seen_index: None },
+            host: "shmeppy-1".to_string(),
+            close: None,
+            end_inferred: false,
+        }
+    }
+
+    #[test]
+    fn by_day_and_version() {
+        let requests = vec![
+            request(UserId::AccountId(1), 1, Some(2)),
+            request(UserId::AccountId(1), 2, Some(2)),
+            request(UserId::AccountId(2), 3, Some(2)),
+            request(UserId::AccountId(2), 4, Some(1)),
+            request(UserId::Anonymous, 5, Some(1)),
+            request(UserId::Anonymous, 6, None),
+        ];
+
+        // In New York the first three were still on the 25th
+        let versions = collect_protocol_versions(
+            &requests, chrono_tz::America::New_York);
+        assert_eq!(serde_json::to_string(&versions).unwrap(), concat!(
+            r#"{"2020-05-25":{"2":{"users":2,"sessions":3}},"#,
+            r#""2020-05-26":{"1":{"users":1,"sessions":2},"#,
+            r#""unknown":{"users":0,"sessions":1}}}"#));
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde::ser::{Serializer, SerializeMap};
+use spet::span::Span;
+
+use shmeppy_log::ids::{GameId, UserId};
+
+use crate::accumulator::push_onto_accumulator;
+use crate::convocation::Convocation;
+use crate::request::Request;
+use crate::timespan::TimeSpan;
+
+
+/**
+ * How one user kept (or didn't keep) their connection to a convocation.
+ */
+#[derive(Debug, Default, PartialEq, Eq, Serialize)]
+pub struct UserReconnects {
+    // Every websocket they had open to the game during the convocation.
+    // All but the first are reconnects.
+    pub connections: usize,
+    pub reconnects: usize,
+
+    // Of those connections, how many picked the game back up where the
+    // client left off (they sent a lastSeenIndex) and how many loaded it
+    // from scratch
+    pub resumed: usize,
+    pub cold_loads: usize,
+
+    // For each reconnect, how long it'd been since their last connection
+    // closed. 0 if the new one opened before the server noticed the old one
+    // was gone.
+    pub seconds_to_reconnect: Vec<i64>,
+}
+
+
+impl UserReconnects {
+    // requests has to be sorted by start
+    fn new(requests: &[&Request]) -> UserReconnects {
+        let mut user = UserReconnects {
+            connections: requests.len(),
+            reconnects: requests.len().saturating_sub(1),
+            ..UserReconnects::default()
+        };
+
+        let mut closed_at: Option<DateTime<Utc>> = None;
+        for request in requests {
+            if request.query.resumed() {
+                user.resumed += 1;
+            } else {
+                user.cold_loads += 1;
+            }
+
+            // Someone with a couple tabs open is only really disconnected
+            // once the last of them closes
+            if let Some(closed_at) = closed_at {
+                user.seconds_to_reconnect.push(
+                    (request.start - closed_at).num_seconds().max(0));
+            }
+            closed_at = closed_at.max(Some(request.end));
+        }
+
+        user
+    }
+}
+
+
+/**
+ * The reconnects of everyone in one convocation.
+ */
+#[derive(Debug)]
+pub struct ConvocationReconnects {
+    pub game_id: GameId,
+    pub during: TimeSpan,
+    pub users: BTreeMap<UserId, UserReconnects>,
+}
+
+
+impl Serialize for ConvocationReconnects {
+    fn serialize<S: Serializer>(&self, serializer: S)
+            -> Result<S::Ok, S::Error> {
+        // Starts and ends are written the same way convocations write them,
+        // so the two can be matched up
+        let mut map = serializer.serialize_map(Some(4))?;
+        map.serialize_entry("game_id", &self.game_id)?;
+        map.serialize_entry("start", &self.during.start().to_rfc3339())?;
+        map.serialize_entry("end", &self.during.end().to_rfc3339())?;
+        map.serialize_entry("users", &self.users)?;
+        map.end()
+    }
+}
+
+
+/**
+ * Works out how often everyone in each convocation reconnected, keyed by
+ * day the same way convocations are.
+ *
+ * A user's connections to a convocation are their requests to its game that
+ * overlap it at all, so the one they were already on when it started counts
+ * as their first.
+ */
+pub fn collect_reconnects(
+        convocations: &BTreeMap<String, Vec<Convocation>>,
+        requests: &[Request])
+        -> BTreeMap<String, Vec<ConvocationReconnects>> {
+    let mut by_game: BTreeMap<GameId, Vec<&Request>> = BTreeMap::new();
+    for request in requests {
+        push_onto_accumulator(&mut by_game, request.game_id, request);
+    }
+    for game_requests in by_game.values_mut() {
+        game_requests.sort_by_key(|request| (request.start, request.request_id));
+    }
+
+    let mut reconnects_by_day = BTreeMap::new();
+    for (day, day_convocations) in convocations {
+        for convocation in day_convocations {
+            let during = &convocation.during;
+            let mut by_user: BTreeMap<UserId, Vec<&Request>> = BTreeMap::new();
+            for &request in by_game.get(&convocation.game_id).into_iter().flatten() {
+                let in_convocation =
+                    convocation.admins.contains(&request.user_id) ||
+                    convocation.players.contains(&request.user_id);
+                if in_convocation && request.start <= *during.end() &&
+                        request.end >= *during.start() {
+                    push_onto_accumulator(&mut by_user, request.user_id,
+                                          request);
+                }
+            }
+
+            push_onto_accumulator(
+                &mut reconnects_by_day,
+                day.clone(),
+                ConvocationReconnects {
+                    game_id: convocation.game_id,
+                    during: *during,
+                    users: by_user.iter()
+                        .map(|(&user_id, requests)| {
+                            (user_id, UserReconnects::new(requests))
+                        })
+                        .collect(),
+                });
+        }
+    }
+
+    reconnects_by_day
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use chrono::{TimeZone, Utc};
+    use spet::span::CreatableSpan;
+
+    use shmeppy_log::ids::UserId;
+    use shmeppy_log::parse::UUID;
+    use crate::convocation::Convocation;
+    use crate::reconnect::{UserReconnects, collect_reconnects};
+    use crate::request::{Request, SocketQuery};
+    use crate::timespan::TimeSpan;
+
+    fn request(id: u128, user: u64, start: (u32, u32), end: (u32, u32),
+               last_seen_index: Option<u64>) -> Request {
+        Request {
+            request_id: UUID(id),
+            start: Utc.ymd(2020, 5, 26).and_hms(start.0, start.1, 0),
+            end: Utc.ymd(2020, 5, 26).and_hms(end.0, end.1, 0),
+            game_id: 5,
+            user_id: UserId::AccountId(user),
+            is_admin: false,
+            query: SocketQuery { version: Some(2), last_seen_index },
+            host: "shmeppy-1".to_string(),
+            close: None,
+            end_inferred: false,
+        }
+    }
+
+    #[test]
+    fn per_user() {
+        let requests = vec![
+            // Drops once, then opens a couple more tabs before closing any
+            request(1, 1, (1, 0), (1, 30), None),
+            request(2, 1, (1, 32), (2, 0), Some(10)),
+            request(3, 1, (1, 50), (2, 30), Some(20)),
+            request(4, 1, (1, 55), (3, 0), Some(30)),
+            // Never drops
+            request(5, 2, (0, 0), (3, 0), None),
+            // Isn't in the convocation at all
+            request(6, 3, (1, 0), (1, 1), None),
+            // Left before it started
+            request(7, 2, (0, 0), (0, 30), Some(5)),
+        ];
+        let mut convocations = BTreeMap::new();
+        convocations.insert("2020-05-26".to_string(), vec![Convocation {
+            game_id: 5,
+            during: TimeSpan::new(Utc.ymd(2020, 5, 26).and_hms(1, 0, 0),
+                                  Utc.ymd(2020, 5, 26).and_hms(2, 45, 0)),
+            admins: vec![UserId::AccountId(1)],
+            players: vec![UserId::AccountId(2)],
+            provisional: false,
+            end_inferred: false,
+        }]);
+
+        let reconnects = collect_reconnects(&convocations, &requests);
+        let users = &reconnects["2020-05-26"][0].users;
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[&UserId::AccountId(1)], UserReconnects {
+            connections: 4,
+            reconnects: 3,
+            resumed: 3,
+            cold_loads: 1,
+            seconds_to_reconnect: vec![120, 0, 0],
+        });
+        assert_eq!(users[&UserId::AccountId(2)], UserReconnects {
+            connections: 1,
+            reconnects: 0,
+            resumed: 0,
+            cold_loads: 1,
+            seconds_to_reconnect: vec![],
+        });
+
+        assert_eq!(serde_json::to_string(&reconnects).unwrap(), concat!(
+            r#"{"2020-05-26":[{"game_id":5,"#,
+            r#""start":"2020-05-26T01:00:00+00:00","#,
+            r#""end":"2020-05-26T02:45:00+00:00","users":{"#,
+            r#""account_id:1":{"connections":4,"reconnects":3,"resumed":3,"#,
+            r#""cold_loads":1,"seconds_to_reconnect":[120,0,0]},"#,
+            r#""account_id:2":{"connections":1,"reconnects":0,"resumed":0,"#,
+            r#""cold_loads":1,"seconds_to_reconnect":[]}}}]}"#));
+    }
+}
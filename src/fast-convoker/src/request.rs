@@ -1,66 +1,114 @@
 use std::collections::BTreeMap;
+use std::fmt;
 
 use chrono::{DateTime, Utc};
 use memmem::{Searcher, TwoWaySearcher};
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::error::Stage;
+use shmeppy_log::ids::{AccountId, GameId, UserId};
+use shmeppy_log::lex::Parts;
+use shmeppy_log::parse::{
+    extract_integer, is_ascii_hex_character, parse_uuid, skip_prefix};
+use shmeppy_log::parse::UUID;
+use shmeppy_log::zones::Zones;
+
+
+/**
+ * What the client put in the query string when it opened a game's websocket,
+ * like /game-socket/381468491?version=2&lastSeenIndex=328. Anything missing
+ * (or that isn't a number) is None.
+ */
+#[derive(PartialEq, Eq, Debug, Default, Clone, Copy, Serialize, Deserialize)]
+pub struct SocketQuery {
+    pub version: Option<u32>,
+
+    // The index of the last operation the client already has. Only sent
+    // when it's picking a game back up rather than loading it from scratch.
+    pub last_seen_index: Option<u64>,
+}
 
-use crate::lex::Parts;
-use crate::parse::{parse_timestamp, parse_uuid};
-use crate::parse::UUID;
 
+impl SocketQuery {
+    /**
+     * Whether the client resumed the game rather than cold-loading it.
+     */
+    pub fn resumed(&self) -> bool {
+        self.last_seen_index.is_some()
+    }
+}
 
-pub type GameId = u64;
-pub type AccountId = u64;
 
+// Parses the query string off the rest of the path, which looks like
+// "?version=2&lastSeenIndex=328 1.1". Parameters we don't know are ignored.
+fn parse_socket_query(raw: &[u8]) -> SocketQuery {
+    let mut query = SocketQuery::default();
+    let raw = match skip_prefix(b"?", raw) {
+        Some(raw) => raw,
+        None => return query,
+    };
+    let end = raw.iter().position(|&c| c == b' ').unwrap_or(raw.len());
 
-#[derive(PartialEq, Eq, Debug)]
-enum Message {
-    // Contains game ID
-    StartedGameId(GameId),
+    for pair in raw[..end].split(|&c| c == b'&') {
+        if let Some(value) = skip_prefix(b"version=", pair) {
+            query.version = parse_whole_integer(value);
+        } else if let Some(value) = skip_prefix(b"lastSeenIndex=", pair) {
+            query.last_seen_index = parse_whole_integer(value);
+        }
+    }
 
-    // Contains analytics ID
-    AnalyticsId(UUID),
+    query
+}
 
-    // Contains account_id and is_admin
-    AuthenticatedAs(AccountId, bool),
 
-    Finished,
+// Like extract_integer, but the integer has to be all there is
+fn parse_whole_integer<T: std::str::FromStr>(raw: &[u8]) -> Option<T> {
+    std::str::from_utf8(raw).ok()?.parse().ok()
 }
 
 
-fn skip_prefix<'a>(prefix: &[u8], from: &'a [u8]) -> Option<&'a [u8]> {
-    if from.len() >= prefix.len() && prefix == &from[..prefix.len()] {
-        Some(&from[prefix.len()..])
-    } else {
-        None
-    }
+/**
+ * How a game's websocket was closed, going by its "Finished websocket: 1005
+ * ''" line. The code's the one from the websocket protocol (1000 is a normal
+ * close, 1001 the client going away, 1005 no code given, 1006 the connection
+ * dropping...) and the reason's whatever text came with it.
+ */
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
+pub struct Close {
+    pub code: u16,
+    pub reason: String,
 }
 
 
-// Extracts a base 10 ascii-encoded integer from the very start of raw. So
-// b"12 hello" would get 12 extracted, but b" 12 hello" would give None.
-fn extract_integer<T: std::str::FromStr>(raw: &[u8]) -> Option<T> {
-    let mut i = 0;
-    while i < raw.len() {
-        if raw[i] < b'0' || raw[i] > b'9' {
-            break;
-        }
+// Parses what's after "Finished websocket: ", which looks like "1005 ''"
+fn parse_close(raw: &[u8]) -> Option<Close> {
+    let code = extract_integer(raw)?;
+    let quoted = &raw[raw.iter().position(|&c| c == b'\'')? + 1..];
+    let reason = &quoted[..quoted.iter().rposition(|&c| c == b'\'')?];
+    Some(Close {
+        code,
+        reason: String::from_utf8_lossy(reason).into_owned(),
+    })
+}
 
-        i += 1;
-    }
 
-    let integer_as_str = unsafe {
-        std::str::from_utf8_unchecked(&raw[..i])
-    };
+#[derive(PartialEq, Eq, Debug)]
+enum Message {
+    // Contains game ID, and what the client asked for when connecting
+    StartedGameId(GameId, SocketQuery),
 
-    integer_as_str.parse::<T>().ok()
+    // Contains analytics ID
+    AnalyticsId(UUID),
+
+    // Contains account_id and is_admin
+    AuthenticatedAs(AccountId, bool),
+
+    // Contains how the websocket was closed, if this line says
+    Finished(Option<Close>),
 }
 
 
 fn extract_account_id_from_old_log(raw: &[u8]) -> Option<AccountId> {
-    fn is_ascii_hex_character(c: u8) -> bool {
-        (b'0' <= c && c <= b'9') || (b'a' <= c && c <= b'f')
-    }
-
     let looking_for = b"{ accountId: '";
     let start = TwoWaySearcher::new(looking_for).search_in(raw)? +
                 looking_for.len();
@@ -93,17 +141,25 @@ fn extract_is_admin_from_old_log(raw: &[u8]) -> Option<bool> {
 }
 
 
+const STARTED_GAME_PREFIX: &[u8] = b"Started websocket: GET /game-socket/";
+const ANALYTICS_ID_PREFIX: &[u8] = b"Analytics ID: ";
+const CLIENT_ADDED_PREFIX: &[u8] = b"Client added to client DB: ";
+
+// The first thing the server logs when it starts up
+const RESTART_PREFIX: &[u8] = b"Listening on port ";
+
+
 fn parse_message(raw: &[u8]) -> Option<Message> {
     use Message::*;
 
-    if let Some(remainder) = skip_prefix(
-            b"Started websocket: GET /game-socket/", raw) {
-        Some(StartedGameId(extract_integer(remainder)?))
-    } else if let Some(remainder) = skip_prefix(
-            b"Analytics ID: ", raw) {
-        Some(AnalyticsId(crate::parse::parse_uuid(remainder)?))
-    } else if let Some(remainder) = skip_prefix(
-            b"Client added to client DB: ", raw) {
+    if let Some(remainder) = skip_prefix(STARTED_GAME_PREFIX, raw) {
+        let game_id = extract_integer(remainder)?;
+        let digits = remainder.iter().take_while(|c| c.is_ascii_digit())
+            .count();
+        Some(StartedGameId(game_id, parse_socket_query(&remainder[digits..])))
+    } else if let Some(remainder) = skip_prefix(ANALYTICS_ID_PREFIX, raw) {
+        Some(AnalyticsId(shmeppy_log::parse::parse_uuid(remainder)?))
+    } else if let Some(remainder) = skip_prefix(CLIENT_ADDED_PREFIX, raw) {
         // This detects whether this is a log message from before I changed the
         // log to be in JSON format.
         if skip_prefix(b"{ gameId", remainder).is_some() {
@@ -133,32 +189,50 @@ fn parse_message(raw: &[u8]) -> Option<Message> {
                 None
             }
         }
-    } else if skip_prefix(b"Finished websocket: ", raw).is_some() ||
-            skip_prefix(b"Finished: ", raw).is_some() ||
+    } else if let Some(remainder) = skip_prefix(b"Finished websocket: ", raw) {
+        Some(Finished(parse_close(remainder)))
+    } else if skip_prefix(b"Finished: ", raw).is_some() ||
             skip_prefix(b"Waiting for client to complete closing handshake.", raw).is_some() {
-        Some(Finished)
+        Some(Finished(None))
     } else {
         None
     }
 }
 
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy, Ord, PartialOrd)]
-pub enum UserId {
-    AnalyticsId(UUID),
-    AccountId(AccountId),
-    Anonymous,
+/**
+ * Whether raw starts like one of the messages we care about, but couldn't
+ * actually be parsed. That's usually a sign the message's format changed out
+ * from under us.
+ */
+pub fn is_malformed_message(raw: &[u8]) -> bool {
+    [STARTED_GAME_PREFIX, ANALYTICS_ID_PREFIX, CLIENT_ADDED_PREFIX].iter()
+        .any(|prefix| skip_prefix(prefix, raw).is_some()) &&
+        parse_message(raw).is_none()
 }
 
 
-#[derive(PartialEq, Eq, Debug, Clone)]
+#[derive(PartialEq, Eq, Debug, Clone, Serialize, Deserialize)]
 pub struct Request {
     pub request_id: UUID,
+    // The logs only go down to the second anyways
+    #[serde(with = "chrono::serde::ts_seconds")]
     pub start: DateTime<Utc>,
+    #[serde(with = "chrono::serde::ts_seconds")]
     pub end: DateTime<Utc>,
     pub game_id: GameId,
     pub user_id: UserId,
     pub is_admin: bool,
+    pub query: SocketQuery,
+
+    // The host that served it, and how it was closed. Requests that never
+    // logged a close code (like ones with inferred ends) don't have one.
+    pub host: String,
+    pub close: Option<Close>,
+
+    // We never saw this request finish, so end is our best guess (see
+    // RequestCollector::infer_ends)
+    pub end_inferred: bool,
 }
 
 
@@ -170,62 +244,234 @@ impl Request {
             end: partial.end?,
             game_id: partial.game_id?,
             is_admin: partial.is_admin?,
-            user_id: match (partial.account_id, partial.analytics_id) {
-                (Some(account_id), _) => UserId::AccountId(account_id),
-                (None, Some(analytics_id)) =>
-                    UserId::AnalyticsId(analytics_id),
-                _ => UserId::Anonymous
-            },
+            user_id: partial.user_id(),
+            query: partial.query,
+            host: String::from_utf8_lossy(partial.host.as_ref()?).into_owned(),
+            close: partial.close.clone(),
+            end_inferred: partial.end_inferred,
         })
     }
 }
 
 
-#[derive(Default)]
+/**
+ * A game's websocket that we saw start but never saw finish. Usually that's
+ * because it was still connected when the logs we were given end.
+ */
+#[derive(PartialEq, Eq, Debug, Clone)]
+pub struct OpenRequest {
+    pub request_id: UUID,
+    pub start: DateTime<Utc>,
+    pub game_id: GameId,
+    pub user_id: UserId,
+
+    // Whether they'd authenticated as an admin yet
+    pub is_admin: bool,
+    pub query: SocketQuery,
+    pub host: String,
+}
+
+
+impl OpenRequest {
+    /**
+     * The request this'll be if it finishes at end.
+     */
+    pub fn until(&self, end: DateTime<Utc>) -> Request {
+        Request {
+            request_id: self.request_id,
+            start: self.start,
+            end,
+            game_id: self.game_id,
+            user_id: self.user_id,
+            is_admin: self.is_admin,
+            query: self.query,
+            host: self.host.clone(),
+            close: None,
+            end_inferred: false,
+        }
+    }
+}
+
+
+/**
+ * How many requests infer_ends gave ends to, by what it went on.
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct InferredEnds {
+    // The server restarted before the request logged anything else
+    pub at_restart: usize,
+
+    // The server restarted, but not until after the request's last line
+    pub at_last_line: usize,
+
+    // Nothing restarted, but the request had been quiet for longer than
+    // --open-timeout, so it ended at its last line too
+    pub timed_out: usize,
+}
+
+
+impl InferredEnds {
+    pub fn total(&self) -> usize {
+        self.at_restart + self.at_last_line + self.timed_out
+    }
+}
+
+
+impl fmt::Display for InferredEnds {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} game request(s) never finished, so their ends were \
+                   inferred ({} at a server restart, {} at their last line \
+                   before one, {} after going quiet)",
+               self.total(), self.at_restart, self.at_last_line,
+               self.timed_out)
+    }
+}
+
+
+#[derive(Default, Clone, Serialize, Deserialize)]
 struct PartialRequest {
     start: Option<DateTime<Utc>>,
     end: Option<DateTime<Utc>>,
     game_id: Option<GameId>,
+    query: SocketQuery,
     analytics_id: Option<UUID>,
     account_id: Option<AccountId>,
     is_admin: Option<bool>,
+    close: Option<Close>,
+
+    // Where the request was served, and the last time it logged anything.
+    // Only used to infer an end if it never gets one.
+    host: Option<Vec<u8>>,
+    last_seen: Option<DateTime<Utc>>,
+    end_inferred: bool,
     // The UUID of the request is associated with this in the hash table, so
     // it's not included here.
 }
 
 
+impl PartialRequest {
+    fn user_id(&self) -> UserId {
+        match (self.account_id, self.analytics_id) {
+            (Some(account_id), _) => UserId::AccountId(account_id),
+            (None, Some(analytics_id)) => UserId::AnalyticsId(analytics_id),
+            _ => UserId::Anonymous
+        }
+    }
+
+    // Fills in what we learned about the request from lines that came after
+    // the ones self was built from.
+    fn absorb(&mut self, later: PartialRequest) {
+        // The first end we see is the real one (see update)
+        if self.end.is_none() {
+            self.end = later.end;
+        }
+        if self.close.is_none() {
+            self.close = later.close;
+        }
+
+        self.analytics_id = later.analytics_id.or(self.analytics_id);
+        self.account_id = later.account_id.or(self.account_id);
+        self.is_admin = later.is_admin.or(self.is_admin);
+        self.last_seen = self.last_seen.max(later.last_seen);
+    }
+}
+
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct RequestCollector {
-    partial_requests: BTreeMap<UUID, PartialRequest>
+    partial_requests: BTreeMap<UUID, PartialRequest>,
+
+    // What we've seen of requests that didn't start in the lines we were
+    // fed. When the logs are split into chunks these are the tail ends of
+    // requests that started in an earlier chunk, and merge fills them into
+    // those requests.
+    orphans: BTreeMap<UUID, PartialRequest>,
+
+    // When each host's server started up, which ends every request it was
+    // serving at the time
+    restarts: BTreeMap<Vec<u8>, Vec<DateTime<Utc>>>,
+
+    #[serde(skip)]
+    zones: Zones,
 }
 
 
 impl RequestCollector {
     pub fn new() -> RequestCollector {
+        RequestCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> RequestCollector {
         RequestCollector {
             partial_requests: BTreeMap::new(),
+            orphans: BTreeMap::new(),
+            restarts: BTreeMap::new(),
+            zones,
         }
     }
 
-    pub fn update(&mut self, parts: &Parts) -> Option<()> {
+    /**
+     * Gives back the stage the line was rejected at, if it's one we care
+     * about and couldn't make sense of.
+     */
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
         use Message::*;
-        let message = parse_message(parts.message)?;
+        if skip_prefix(RESTART_PREFIX, &parts.message).is_some() {
+            let at = self.zones.parse(parts.timestamp)
+                .ok_or(Stage::ParseTimestamp)?;
+            self.restarts.entry(parts.host.to_vec()).or_default().push(at);
+            return Ok(());
+        }
+
+        // Lines logged outside of any request (ie: on startup) don't have a
+        // UUID at all, which is fine.
+        if parts.uuid.is_empty() {
+            return Ok(());
+        }
+        let uuid = parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?;
+
+        // Any line at all counts as the request being seen, not just the
+        // ones we pick apart below, but lines we don't otherwise care about
+        // only get a look if we already know about the request. Otherwise
+        // we'd be keeping something for every request in the logs.
+        let message = parse_message(&parts.message);
+        if message.is_none() {
+            if is_malformed_message(&parts.message) {
+                return Err(Stage::ParseMessage);
+            } else if !self.partial_requests.contains_key(&uuid) &&
+                    !self.orphans.contains_key(&uuid) {
+                return Ok(());
+            }
+        }
+
+        let at = Some(self.zones.parse(parts.timestamp)
+                      .ok_or(Stage::ParseTimestamp)?);
 
         // Grabs the request object for this request, or makes it if this is
         // a "StartedGameId" message.
         let partial_request: &mut PartialRequest = {
-            let uuid = parse_uuid(parts.uuid)?;
-            if let StartedGameId(_) = message {
+            if let Some(StartedGameId(..)) = message {
                 self.partial_requests.insert(uuid,
                                              PartialRequest::default());
             }
 
-            self.partial_requests.get_mut(&uuid)?
+            match self.partial_requests.get_mut(&uuid) {
+                Some(partial_request) => partial_request,
+                None => self.orphans.entry(uuid).or_default(),
+            }
         };
 
+        partial_request.last_seen = partial_request.last_seen.max(at);
+        let message = match message {
+            Some(message) => message,
+            None => return Ok(()),
+        };
         match message {
-            StartedGameId(game_id) => {
+            StartedGameId(game_id, query) => {
                 partial_request.game_id = Some(game_id);
-                partial_request.start = parse_timestamp(parts.timestamp);
+                partial_request.query = query;
+                partial_request.start = at;
+                partial_request.host = Some(parts.host.to_vec());
             },
             AnalyticsId(analytics_id) => {
                 partial_request.analytics_id = Some(analytics_id);
@@ -234,18 +480,127 @@ impl RequestCollector {
                 partial_request.account_id = Some(account_id);
                 partial_request.is_admin = Some(is_admin);
             }
-            Finished => {
+            Finished(close) => {
                 // We look for a few messages as the "end" of a request, and
                 // it's common to have them appear more than once. So we wanna
                 // make sure to just call the request ended once we hit the
                 // first one.
                 if partial_request.end.is_none() {
-                    partial_request.end = parse_timestamp(parts.timestamp);
+                    partial_request.end = at;
+                }
+
+                // The line with the close code isn't always the first of
+                // them though
+                if partial_request.close.is_none() {
+                    partial_request.close = close;
                 }
             },
         }
 
-        Some(())
+        Ok(())
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: RequestCollector) {
+        for (uuid, orphan) in later.orphans {
+            match self.partial_requests.get_mut(&uuid) {
+                Some(partial_request) => partial_request.absorb(orphan),
+                None => self.orphans.entry(uuid).or_default().absorb(orphan),
+            }
+        }
+
+        // A request starting again replaces it entirely, same as in update
+        self.partial_requests.extend(later.partial_requests);
+
+        for (host, restarts) in later.restarts {
+            self.restarts.entry(host).or_default().extend(restarts);
+        }
+    }
+
+    /**
+     * Throws away the orphans. Once everything before this collector's lines
+     * has been merged in, nothing will ever claim them.
+     */
+    pub fn forget_orphans(&mut self) {
+        self.orphans.clear();
+    }
+
+    /**
+     * Gives requests that never finished an end, and counts how many did.
+     *
+     * A request can't have outlived the server it was on, so one with a
+     * restart on its host after it started ended at its last line or that
+     * restart, whichever came first. last_active has the last time each
+     * request committed anything, which counts as one of its lines.
+     *
+     * Requests without a restart after them might really still be going, so
+     * they're left open unless their last line was before quiet_before (ie:
+     * they've been quiet for longer than --open-timeout).
+     */
+    pub fn infer_ends(&mut self, last_active: &BTreeMap<UUID, DateTime<Utc>>,
+                      quiet_before: Option<DateTime<Utc>>) -> InferredEnds {
+        for restarts in self.restarts.values_mut() {
+            restarts.sort_unstable();
+        }
+
+        let all_restarts = &self.restarts;
+        let mut inferred = InferredEnds::default();
+        for (uuid, partial) in self.partial_requests.iter_mut() {
+            let start = match (partial.start, partial.end) {
+                (Some(start), None) => start,
+                _ => continue,
+            };
+
+            let last_line = partial.last_seen.max(last_active.get(uuid).copied())
+                .unwrap_or(start);
+            let restart = partial.host.as_ref()
+                .and_then(|host| all_restarts.get(host))
+                .and_then(|restarts| {
+                    restarts.iter().find(|&&restart| restart > start).copied()
+                });
+            partial.end = match restart {
+                Some(restart) if restart < last_line => {
+                    inferred.at_restart += 1;
+                    Some(restart)
+                },
+                Some(_) => {
+                    inferred.at_last_line += 1;
+                    Some(last_line)
+                },
+                None if quiet_before.is_some_and(|quiet| last_line < quiet) => {
+                    inferred.timed_out += 1;
+                    Some(last_line)
+                },
+                None => continue,
+            };
+            partial.end_inferred = true;
+        }
+
+        inferred
+    }
+
+    /**
+     * The games' requests that never finished. into_requests leaves these
+     * out, since there's no telling how long they really went on for
+     * (unless infer_ends guesses).
+     */
+    pub fn open_requests(&self) -> Vec<OpenRequest> {
+        self.partial_requests.iter()
+            .filter(|(_, partial)| partial.end.is_none())
+            .filter_map(|(uuid, partial)| Some(OpenRequest {
+                request_id: *uuid,
+                start: partial.start?,
+                game_id: partial.game_id?,
+                user_id: partial.user_id(),
+                is_admin: partial.is_admin.unwrap_or(false),
+                query: partial.query,
+                host: String::from_utf8_lossy(partial.host.as_ref()?)
+                    .into_owned(),
+            }))
+            .collect()
     }
 
     pub fn into_requests(self) -> impl Iterator<Item = Request> {
@@ -260,18 +615,26 @@ impl RequestCollector {
 }
 
 
+impl Default for RequestCollector {
+    fn default() -> RequestCollector {
+        RequestCollector::new()
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
     mod request_collector {
-        use crate::request::{RequestCollector, Request, UserId};
-        use crate::lex::locate_parts;
-        use crate::parse::UUID;
+        use shmeppy_log::ids::UserId;
+        use shmeppy_log::lex::locate_parts;
+        use shmeppy_log::parse::UUID;
+        use crate::request::{
+            Close, InferredEnds, RequestCollector, Request, SocketQuery};
         use chrono::Utc;
         use chrono::offset::TimeZone;
 
-        #[test]
-        fn complete_and_partial() {
-            let raw_logs: Vec<&[u8]> = vec![
+        fn complete_request_logs() -> Vec<&'static [u8]> {
+            vec![
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/381468491?version=2&lastSeenIndex=328 1.1"#,
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Analytics ID: 45e0e69c-ddd9-4443-abab-f3b46e47a62b"#,
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:35:08 AM] Successfully authenticated as: { accountId: 'c7d585cd803aafa5', displayName: 'John' }"#,
@@ -295,11 +658,15 @@ mod tests {
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [DEBUG - 5/26/2020 1:36:47 AM] Waiting for client to complete closing handshake."#,
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [DEBUG - 5/26/2020 1:36:49 AM] Waiting for client to complete closing handshake."#,
                 br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:36:50 AM] Finished websocket: 1005 ''"#,
-            ];
+            ]
+        }
 
+        #[test]
+        fn complete_and_partial() {
+            let raw_logs = complete_request_logs();
             let mut collector = RequestCollector::new();
             for raw_log in raw_logs {
-                collector.update(&locate_parts(raw_log).unwrap());
+                collector.update(&locate_parts(raw_log).unwrap()).unwrap();
             }
 
             let partial_requests: Vec<_> =
@@ -315,12 +682,131 @@ mod tests {
                 game_id: 381468491,
                 user_id: UserId::AccountId(0xc7d585cd803aafa5),
                 is_admin: true,
+                query: SocketQuery {
+                    version: Some(2),
+                    last_seen_index: Some(328),
+                },
+                host: "shmeppy-1".to_string(),
+                close: Some(Close { code: 1005, reason: String::new() }),
+                end_inferred: false,
             };
             assert_eq!(maybe_request, Some(expected_request.clone()));
 
             assert_eq!(collector.into_requests().collect::<Vec<_>>(),
                        vec![expected_request]);
         }
+
+        #[test]
+        fn merge_at_every_split() {
+            let mut raw_logs = complete_request_logs();
+            // The same request starting over, which should replace the first
+            raw_logs.extend(vec![
+                br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 2:00:00 AM] Started websocket: GET /game-socket/1 1.1"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 2:00:01 AM] Client added to client DB: {"gameId":1,"clientId":1,"account":{"accountId":"00000000000000ff","displayName":"Jo"},"isAdmin":false,"lastPing":1590456908483}"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 2:10:00 AM] Finished websocket: 1005 ''"#.as_ref(),
+            ]);
+
+            let collect = |lines: &[&[u8]]| {
+                let mut collector = RequestCollector::new();
+                for line in lines {
+                    collector.update(&locate_parts(line).unwrap()).unwrap();
+                }
+                collector
+            };
+            let expected: Vec<Request> =
+                collect(&raw_logs).into_requests().collect();
+            assert_eq!(expected.len(), 1);
+            assert_eq!(expected[0].game_id, 1);
+
+            for split in 0..=raw_logs.len() {
+                for second_split in split..=raw_logs.len() {
+                    let mut merged = collect(&raw_logs[..split]);
+                    merged.forget_orphans();
+                    merged.merge(collect(&raw_logs[split..second_split]));
+                    merged.merge(collect(&raw_logs[second_split..]));
+                    assert_eq!(merged.into_requests().collect::<Vec<_>>(),
+                               expected, "split at {} {}", split,
+                               second_split);
+                }
+            }
+        }
+
+        #[test]
+        fn infer_ends() {
+            use std::collections::BTreeMap;
+
+            // Three requests that never finish: one the server restarts out
+            // from under, one that's quiet for a while before the restart,
+            // and one on another host that nothing restarts
+            let mut collector = RequestCollector::new();
+            for raw_log in [
+                br#"shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/1 1.1"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Client added to client DB: {"gameId":1,"clientId":1,"account":{"accountId":"00000000000000ff","displayName":"A"},"isAdmin":true}"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: (257edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/1 1.1"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: (257edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:05:00 AM] Client added to client DB: {"gameId":1,"clientId":2,"account":{"accountId":"00000000000000fe","displayName":"B"},"isAdmin":false}"#.as_ref(),
+                br#"shmeppy-2 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/2 1.1"#.as_ref(),
+                br#"shmeppy-2 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:10:00 AM] Client added to client DB: {"gameId":2,"clientId":3,"account":{"accountId":"00000000000000fd","displayName":"C"},"isAdmin":true}"#.as_ref(),
+                br#"shmeppy-1 shmeppy-app: [INFO - 5/26/2020 2:00:00 AM] Listening on port 8080"#.as_ref(),
+            ].iter() {
+                collector.update(&locate_parts(raw_log).unwrap()).unwrap();
+            }
+
+            // The first request kept committing things right up until the
+            // restart (and, as far as the logs say, past it)
+            let mut last_active = BTreeMap::new();
+            last_active.insert(UUID(0x157edc79_03fe_4787_910a_d2f14302348c),
+                               Utc.ymd(2020, 5, 26).and_hms(2, 30, 0));
+
+            let mut quiet = collector.clone();
+            let inferred = collector.infer_ends(&last_active, None);
+            assert_eq!(inferred, InferredEnds {
+                at_restart: 1,
+                at_last_line: 1,
+                timed_out: 0,
+            });
+            let ends: Vec<_> = collector.into_requests()
+                .map(|request| (request.request_id, request.end,
+                                request.end_inferred))
+                .collect();
+            assert_eq!(ends, vec![
+                (UUID(0x157edc79_03fe_4787_910a_d2f14302348c),
+                 Utc.ymd(2020, 5, 26).and_hms(2, 0, 0), true),
+                (UUID(0x257edc79_03fe_4787_910a_d2f14302348c),
+                 Utc.ymd(2020, 5, 26).and_hms(1, 5, 0), true),
+            ]);
+
+            // Without anything to go on the last one could still be open,
+            // unless it's been quiet for long enough
+            let inferred = quiet.infer_ends(
+                &last_active, Some(Utc.ymd(2020, 5, 26).and_hms(1, 30, 0)));
+            assert_eq!(inferred.timed_out, 1);
+            assert_eq!(inferred.total(), 3);
+            let last = quiet.into_requests().last().unwrap();
+            assert_eq!(last.end, Utc.ymd(2020, 5, 26).and_hms(1, 10, 0));
+            assert!(last.end_inferred);
+        }
+
+        #[test]
+        fn any_line_counts_as_seen() {
+            use std::collections::BTreeMap;
+
+            let lines: Vec<&[u8]> = vec![
+                br#"shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/1 1.1"#,
+                br#"shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Client added to client DB: {"gameId":1,"clientId":1,"account":{"accountId":"00000000000000ff","displayName":"A"},"isAdmin":true}"#,
+                // Nothing we pick apart, but the request's still around
+                br#"shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:20:00 AM] Socket init complete"#,
+                br#"shmeppy-1 shmeppy-app: [INFO - 5/26/2020 2:00:00 AM] Listening on port 8080"#,
+            ];
+
+            let mut collector = RequestCollector::new();
+            for line in &lines {
+                collector.update(&locate_parts(line).unwrap()).unwrap();
+            }
+            assert_eq!(collector.infer_ends(&BTreeMap::new(), None).at_last_line,
+                       1);
+            let request = collector.into_requests().next().unwrap();
+            assert_eq!(request.end, Utc.ymd(2020, 5, 26).and_hms(1, 20, 0));
+        }
     }
 
     mod extract_is_admin_from_old_log {
@@ -380,8 +866,8 @@ mod tests {
     }
 
     mod parse_message {
-        use crate::request::{parse_message, Message::*};
-        use crate::parse::UUID;
+        use shmeppy_log::parse::UUID;
+        use crate::request::{parse_message, Close, Message::*, SocketQuery};
 
         #[test]
         fn no_match() {
@@ -390,15 +876,27 @@ mod tests {
 
         #[test]
         fn started_game_id() {
+            let query = |version, last_seen_index| SocketQuery {
+                version,
+                last_seen_index,
+            };
             assert_eq!(
                 parse_message(b"Started websocket: GET /game-socket/92829"),
-                Some(StartedGameId(92829)));
+                Some(StartedGameId(92829, query(None, None))));
             assert_eq!(
                 parse_message(b"Started websocket: GET /game-socket/92829?version=1"),
-                Some(StartedGameId(92829)));
+                Some(StartedGameId(92829, query(Some(1), None))));
             assert_eq!(
                 parse_message(b"Started websocket: GET /game-socket/92829?version=1&lastSeenIndex=101"),
-                Some(StartedGameId(92829)));
+                Some(StartedGameId(92829, query(Some(1), Some(101)))));
+            assert_eq!(
+                parse_message(b"Started websocket: GET /game-socket/92829?lastSeenIndex=0&version=2 1.1"),
+                Some(StartedGameId(92829, query(Some(2), Some(0)))));
+
+            // A bad parameter is left out without losing the game ID
+            assert_eq!(
+                parse_message(b"Started websocket: GET /game-socket/92829?version=two&lastSeenIndex=&foo=1 1.1"),
+                Some(StartedGameId(92829, query(None, None))));
         }
 
         #[test]
@@ -458,87 +956,32 @@ mod tests {
 
         #[test]
         fn finished() {
+            let close = |code, reason: &str| Some(Close {
+                code,
+                reason: reason.to_string(),
+            });
             assert_eq!(parse_message(b"Finished websocket: 1005 ''"),
-                       Some(Finished));
-            assert_eq!(parse_message(b"Finished: 200 'OK'"), Some(Finished));
-            assert_eq!(parse_message(b"Waiting for client to complete closing handshake."), Some(Finished));
+                       Some(Finished(close(1005, ""))));
+            assert_eq!(parse_message(b"Finished websocket: 4000 'it's over'"),
+                       Some(Finished(close(4000, "it's over"))));
+            assert_eq!(parse_message(b"Finished websocket: 1001"),
+                       Some(Finished(None)));
+            assert_eq!(parse_message(b"Finished: 200 'OK'"),
+                       Some(Finished(None)));
+            assert_eq!(parse_message(b"Waiting for client to complete closing handshake."), Some(Finished(None)));
         }
-    }
-
-    mod extract_integer {
-        use crate::request::extract_integer;
 
         #[test]
-        fn simple_u8() {
-            assert_eq!(extract_integer::<u8>(b"12"), Some(12));
-            assert_eq!(extract_integer::<u8>(b"12 hello"), Some(12));
-            assert_eq!(extract_integer::<u8>(b"0 hello"), Some(0));
-            assert_eq!(extract_integer::<u8>(b"255 hello"), Some(255));
-        }
-
-        #[test]
-        fn overflow_u8() {
-            assert_eq!(extract_integer::<u8>(b"256 hello"), None);
-        }
-
-        #[test]
-        fn space_in_front() {
-            assert_eq!(extract_integer::<u8>(b" 12 hello"), None);
-        }
-
-        #[test]
-        fn empty() {
-            assert_eq!(extract_integer::<u8>(b""), None);
+        fn malformed() {
+            use crate::request::is_malformed_message;
+
+            assert!(is_malformed_message(b"Started websocket: GET /game-socket/abc"));
+            assert!(is_malformed_message(b"Analytics ID: nope"));
+            assert!(is_malformed_message(br#"Client added to client DB: {}"#));
+            assert!(!is_malformed_message(b"Started websocket: GET /game-socket/1"));
+            assert!(!is_malformed_message(b"Committed 1 operation(s)."));
+            assert!(!is_malformed_message(b""));
         }
     }
 
-    mod skip_prefix {
-        use crate::request::skip_prefix;
-
-        #[test]
-        fn prefix_present() {
-            let text = b"HelloWorld";
-            let prefix = b"Hello";
-            let result = skip_prefix(prefix, text);
-            assert!(result.is_some());
-            assert_eq!(result.unwrap(), b"World");
-            assert_eq!(result.unwrap().as_ptr(), text[5..].as_ptr());
-        }
-
-        #[test]
-        fn prefix_missing() {
-            let text = b"HelloWorld";
-            let prefix = b"Goodbye";
-            let result = skip_prefix(prefix, text);
-            assert!(result.is_none());
-        }
-
-        #[test]
-        fn empty_prefix() {
-            let text = b"HelloWorld";
-            let prefix = b"";
-            let result = skip_prefix(prefix, text);
-            assert!(result.is_some());
-            assert_eq!(result.unwrap(), b"HelloWorld");
-            assert_eq!(result.unwrap().as_ptr(), text.as_ptr());
-        }
-
-        #[test]
-        fn empty_text() {
-            let text = b"";
-            let prefix = b"Hello";
-            let result = skip_prefix(prefix, text);
-            assert!(result.is_none());
-        }
-
-        #[test]
-        fn empty_both() {
-            let text = b"";
-            let prefix = b"";
-            let result = skip_prefix(prefix, text);
-            assert!(result.is_some());
-            assert_eq!(result.unwrap(), b"");
-            assert_eq!(result.unwrap().as_ptr(), text.as_ptr());
-        }
-    }
 }
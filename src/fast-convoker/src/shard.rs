@@ -0,0 +1,353 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::daterange::DateRange;
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::Error;
+
+use crate::analysis::{Analysis, Report};
+use crate::compact::CompactActiveUsers;
+use crate::options::Options;
+use crate::output::write_atomically;
+
+
+/**
+ * One month of one analysis, ready to be written out.
+ */
+#[derive(Debug)]
+pub struct Shard {
+    pub file_name: String,
+
+    // The first and last days that actually have anything in this shard
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+
+    pub contents: Vec<u8>,
+}
+
+
+/**
+ * What manifest.json says about one shard.
+ */
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub file: String,
+    pub first_day: NaiveDate,
+    pub last_day: NaiveDate,
+}
+
+
+impl Shard {
+    pub fn manifest_entry(&self) -> ManifestEntry {
+        ManifestEntry {
+            file: self.file_name.clone(),
+            first_day: self.first_day,
+            last_day: self.last_day,
+        }
+    }
+}
+
+
+// Analysis names to the shards written for them
+type Manifest = BTreeMap<String, Vec<ManifestEntry>>;
+
+
+// A month's worth of something, and the days it's seen
+struct Month<T> {
+    first_day: NaiveDate,
+    last_day: NaiveDate,
+    contents: T,
+}
+
+
+// Months by YYYY-MM
+type Months<T> = BTreeMap<String, Month<T>>;
+
+
+// The contents of the month day falls in, making it if need be
+fn month_of<T: Default>(months: &mut Months<T>,
+                        day: NaiveDate) -> &mut T {
+    let month = months.entry(day.format("%Y-%m").to_string())
+        .or_insert_with(|| Month {
+            first_day: day,
+            last_day: day,
+            contents: T::default(),
+        });
+    month.first_day = month.first_day.min(day);
+    month.last_day = month.last_day.max(day);
+    &mut month.contents
+}
+
+
+// Not every analysis zero-pads its days, so these are parsed rather than
+// sliced
+fn parse_day(day: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(day, "%Y-%m-%d").ok()
+}
+
+
+// For analyses that look like {day: ...}. Days that aren't days can't be
+// put in any month, but they never show up anyways.
+fn split<K: Ord, V>(days: &BTreeMap<K, V>,
+                    day_of: impl Fn(&K) -> Option<NaiveDate>)
+        -> Months<BTreeMap<&K, &V>> {
+    let mut months = BTreeMap::new();
+    for (key, value) in days {
+        if let Some(day) = day_of(key) {
+            let contents: &mut BTreeMap<_, _> = month_of(&mut months, day);
+            contents.insert(key, value);
+        }
+    }
+    months
+}
+
+
+// For analyses that look like {something: {day: ...}}
+fn split_nested<O: Ord, K: Ord, V>(by_outer: &BTreeMap<O, BTreeMap<K, V>>,
+                                   day_of: impl Fn(&K) -> Option<NaiveDate>)
+        -> Months<BTreeMap<&O, BTreeMap<&K, &V>>> {
+    let mut months = BTreeMap::new();
+    for (outer, days) in by_outer {
+        for (key, value) in days {
+            if let Some(day) = day_of(key) {
+                let contents: &mut BTreeMap<_, BTreeMap<_, _>> =
+                    month_of(&mut months, day);
+                contents.entry(outer).or_default().insert(key, value);
+            }
+        }
+    }
+    months
+}
+
+
+fn into_shards<T: Serialize>(analysis: Analysis,
+                             months: Months<T>)
+        -> serde_json::Result<Vec<Shard>> {
+    months.into_iter()
+        .map(|(month, Month { first_day, last_day, contents })| Ok(Shard {
+            file_name: format!("{}-{}.json", analysis.name(), month),
+            first_day,
+            last_day,
+            contents: serde_json::to_vec(&contents)?,
+        }))
+        .collect()
+}
+
+
+/**
+ * Splits one analysis up by month. Each shard looks just like the whole
+ * analysis would (compacted, for active users, if options says to), only
+ * with fewer days in it. Panics if the analysis wasn't run.
+ */
+pub fn shards(report: &Report, analysis: Analysis, options: &Options)
+        -> serde_json::Result<Vec<Shard>> {
+    match analysis {
+        Analysis::ActiveUsers if options.compact_active_users => {
+            let months = split(report.active_users.as_ref().unwrap(),
+                               |day| parse_day(day));
+            into_shards(analysis, months.into_iter()
+                .map(|(name, month)| (name, Month {
+                    first_day: month.first_day,
+                    last_day: month.last_day,
+                    contents: CompactActiveUsers::new(month.contents),
+                }))
+                .collect())
+        },
+        Analysis::Convocations => into_shards(
+            analysis,
+            split(report.convocations.as_ref().unwrap(), |day| parse_day(day))),
+        Analysis::ActiveUsers => into_shards(
+            analysis,
+            split(report.active_users.as_ref().unwrap(), |day| parse_day(day))),
+        Analysis::Registrations => into_shards(
+            analysis,
+            split(report.registrations.as_ref().unwrap(), DateString::to_date)),
+        Analysis::EventCounts => into_shards(
+            analysis,
+            split_nested(report.event_counts.as_ref().unwrap(),
+                         DateString::to_date)),
+        Analysis::Websockets => into_shards(
+            analysis,
+            split_nested(report.websockets.as_ref().unwrap(),
+                         DateString::to_date)),
+        Analysis::Reconnects => into_shards(
+            analysis,
+            split(report.reconnects.as_ref().unwrap(), |day| parse_day(day))),
+        Analysis::ProtocolVersions => into_shards(
+            analysis,
+            split(report.protocol_versions.as_ref().unwrap(),
+                  |day| parse_day(day))),
+        Analysis::CloseCodes => into_shards(
+            analysis,
+            split(report.close_codes.as_ref().unwrap(), |day| parse_day(day))),
+        Analysis::Endpoints => into_shards(
+            analysis,
+            split_nested(report.endpoints.as_ref().unwrap(),
+                         DateString::to_date)),
+    }
+}
+
+
+// Leaves the file alone if it already has contents in it, so anything
+// watching the directory only sees the months that changed
+fn write_if_changed(path: &Path, contents: &[u8]) -> io::Result<()> {
+    match fs::read(path) {
+        Ok(existing) if existing == contents => Ok(()),
+        _ => write_atomically(path, |writer| writer.write_all(contents)),
+    }
+}
+
+
+// Whether the report has nothing from anywhere in entry's days, meaning
+// they weren't looked at this time around
+fn outside(dates: &DateRange, entry: &ManifestEntry) -> bool {
+    let before = dates.since.and_then(|since| since.to_date())
+        .is_some_and(|since| entry.last_day < since);
+    let after = dates.until.and_then(|until| until.to_date())
+        .is_some_and(|until| entry.first_day > until);
+    before || after
+}
+
+
+// Whatever manifest was written last time. One that's missing or can't be
+// read is just written over.
+fn read_manifest(path: &Path) -> Manifest {
+    fs::read(path).ok()
+        .and_then(|contents| serde_json::from_slice(&contents).ok())
+        .unwrap_or_default()
+}
+
+
+/**
+ * Writes each analysis into dir as one file per month, along with a
+ * manifest.json listing them all like
+ * {"convocations": [{"file": ..., "first_day": ..., "last_day": ...}]}.
+ *
+ * The manifest's written last, so it never lists a shard that isn't there
+ * yet. Shards from an earlier run that are entirely outside --since and
+ * --until weren't recomputed, so they're left where they are and stay in
+ * the manifest. Every other month is replaced by what's in the report.
+ */
+pub fn write_shards(dir: &Path, analyses: &[Analysis], report: &Report,
+                    options: &Options) -> Result<(), Error> {
+    let manifest_path = dir.join("manifest.json");
+    let mut previous = read_manifest(&manifest_path);
+    let mut manifest = Manifest::new();
+    for &analysis in analyses {
+        let shards = shards(report, analysis, options)?;
+        for shard in &shards {
+            write_if_changed(&dir.join(&shard.file_name), &shard.contents)?;
+        }
+
+        let mut entries: Vec<ManifestEntry> =
+            shards.iter().map(Shard::manifest_entry).collect();
+        let kept: Vec<ManifestEntry> = previous.remove(analysis.name())
+            .unwrap_or_default().into_iter()
+            .filter(|old| {
+                outside(&options.filter.dates, old) &&
+                    entries.iter().all(|new| new.file != old.file)
+            })
+            .collect();
+        entries.extend(kept);
+        entries.sort_by(|a, b| a.file.cmp(&b.file));
+        manifest.insert(analysis.name().to_string(), entries);
+    }
+
+    write_if_changed(&manifest_path, &serde_json::to_vec(&manifest)?)?;
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::fs;
+
+    use shmeppy_log::datestring::DateString;
+    use crate::analysis::{Analysis, Report};
+    use crate::options::Options;
+    use crate::shard::write_shards;
+
+    #[test]
+    fn by_month() {
+        let dir = std::env::temp_dir().join(format!(
+            "fast-convoker-shard-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+
+        let mut registrations = BTreeMap::new();
+        registrations.insert(DateString(*b"2020-05-26"), 1);
+        registrations.insert(DateString(*b"2020-05-31"), 2);
+        registrations.insert(DateString(*b"2020-06-01"), 3);
+        let mut events = BTreeMap::new();
+        events.insert("login", registrations.clone());
+        let mut active_users = BTreeMap::new();
+        active_users.insert("2020-6-2".to_string(), BTreeMap::new());
+        let mut report = Report {
+            registrations: Some(registrations),
+            event_counts: Some(events),
+            active_users: Some(active_users),
+            ..Report::default()
+        };
+        let analyses = [Analysis::Registrations, Analysis::EventCounts,
+                        Analysis::ActiveUsers];
+        let options = Options::default();
+        write_shards(&dir, &analyses, &report, &options).unwrap();
+
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(read("registrations-2020-05.json"),
+                   r#"{"2020-05-26":1,"2020-05-31":2}"#);
+        assert_eq!(read("registrations-2020-06.json"), r#"{"2020-06-01":3}"#);
+        assert_eq!(read("event-counts-2020-06.json"),
+                   r#"{"login":{"2020-06-01":3}}"#);
+        assert_eq!(read("active-users-2020-06.json"), r#"{"2020-6-2":{}}"#);
+        assert_eq!(read("manifest.json"), concat!(
+            r#"{"active-users":[{"file":"active-users-2020-06.json","#,
+            r#""first_day":"2020-06-02","last_day":"2020-06-02"}],"#,
+            r#""event-counts":[{"file":"event-counts-2020-05.json","#,
+            r#""first_day":"2020-05-26","last_day":"2020-05-31"},"#,
+            r#"{"file":"event-counts-2020-06.json","#,
+            r#""first_day":"2020-06-01","last_day":"2020-06-01"}],"#,
+            r#""registrations":[{"file":"registrations-2020-05.json","#,
+            r#""first_day":"2020-05-26","last_day":"2020-05-31"},"#,
+            r#"{"file":"registrations-2020-06.json","#,
+            r#""first_day":"2020-06-01","last_day":"2020-06-01"}]}"#));
+
+        // Only June changes, so May shouldn't be touched
+        let may = dir.join("registrations-2020-05.json");
+        let written_at = fs::metadata(&may).unwrap().modified().unwrap();
+        report.registrations.as_mut().unwrap()
+            .insert(DateString(*b"2020-06-02"), 4);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        write_shards(&dir, &analyses, &report, &options).unwrap();
+        assert_eq!(fs::metadata(&may).unwrap().modified().unwrap(), written_at);
+        assert_eq!(read("registrations-2020-06.json"),
+                   r#"{"2020-06-01":3,"2020-06-02":4}"#);
+
+        // An incremental run only has June in it, but May's still listed
+        let mut june = BTreeMap::new();
+        june.insert(DateString(*b"2020-06-03"), 5);
+        let incremental = Report {
+            registrations: Some(june),
+            ..Report::default()
+        };
+        let mut since_june = Options::default();
+        since_june.filter.dates.since = Some(DateString(*b"2020-06-01"));
+        write_shards(&dir, &[Analysis::Registrations], &incremental,
+                     &since_june).unwrap();
+        assert_eq!(fs::metadata(&may).unwrap().modified().unwrap(), written_at);
+        assert_eq!(read("registrations-2020-06.json"), r#"{"2020-06-03":5}"#);
+        assert_eq!(read("manifest.json"), concat!(
+            r#"{"registrations":[{"file":"registrations-2020-05.json","#,
+            r#""first_day":"2020-05-26","last_day":"2020-05-31"},"#,
+            r#"{"file":"registrations-2020-06.json","#,
+            r#""first_day":"2020-06-03","last_day":"2020-06-03"}]}"#));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,455 @@
+use std::io;
+use std::path::Path;
+
+use chrono::NaiveDate;
+use rusqlite::{Connection, Transaction, params};
+use spet::span::Span;
+
+use shmeppy_log::error::Error;
+
+use crate::analysis::Report;
+use crate::presence::collect_presences;
+use crate::request::Request;
+
+
+/**
+ * Every table we write to, and what's in them.
+ *
+ * Times are RFC 3339 in UTC (which SQLite's date functions understand), and
+ * days are YYYY-MM-DD in --report-tz. User IDs are written the same way as
+ * in the JSON files: account_id:N, analytics_id:N or anonymous.
+ *
+ * Every row is keyed by what it describes rather than by when it was
+ * written, so running again over the same (or more) logs updates rows in
+ * place instead of duplicating them. Rows are never deleted though, so
+ * anything that's stopped showing up (ie: a provisional convocation that
+ * turned out to be too short) sticks around until it's deleted by hand.
+ *
+ * The comments below make it into the database too, so `.schema` in the
+ * sqlite3 shell shows all of this.
+ */
+pub const SCHEMA: &str = "
+-- Each websocket connection to a game that we saw start and finish. Requests
+-- that were still open when a --follow report was written end when the
+-- report was written, and get updated as they go on.
+CREATE TABLE IF NOT EXISTS requests (
+    request_id TEXT PRIMARY KEY, -- as it appears in the logs
+    game_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    is_admin INTEGER NOT NULL,
+    -- from the websocket's query string, NULL if the client didn't send them
+    version INTEGER,
+    last_seen_index INTEGER,
+    host TEXT,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    -- never finished, so ended_at is its last line or the server restarting
+    end_inferred INTEGER NOT NULL DEFAULT 0,
+    -- the websocket's close code and reason, NULL if they weren't logged
+    close_code INTEGER,
+    close_reason TEXT
+);
+
+-- When each user was in each game, with their overlapping requests merged
+-- together. Someone who was both an admin and a player in the same game
+-- has separate presences for each.
+CREATE TABLE IF NOT EXISTS presences (
+    game_id INTEGER NOT NULL,
+    user_id TEXT NOT NULL,
+    is_admin INTEGER NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    PRIMARY KEY (game_id, user_id, is_admin, started_at)
+);
+
+-- Gatherings of at least --min-people people (three by default) in a game
+-- while something was happening in it. Which gatherings count also depends
+-- on --max-gap, --min-length and --max-break, so compare these across runs
+-- made with the same ones.
+CREATE TABLE IF NOT EXISTS convocations (
+    game_id INTEGER NOT NULL,
+    started_at TEXT NOT NULL,
+    ended_at TEXT NOT NULL,
+    day TEXT NOT NULL, -- the day it started on
+    provisional INTEGER NOT NULL, -- still going when this was written
+    -- someone in it has a request with an inferred end
+    end_inferred INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (game_id, started_at)
+);
+
+-- Who was in each convocation. Admins aren't also listed as players.
+CREATE TABLE IF NOT EXISTS convocation_participants (
+    game_id INTEGER NOT NULL,
+    started_at TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    is_admin INTEGER NOT NULL,
+    PRIMARY KEY (game_id, started_at, user_id),
+    FOREIGN KEY (game_id, started_at)
+        REFERENCES convocations (game_id, started_at)
+);
+
+-- How long each GM spent in an active game each day. Days they didn't
+-- aren't written.
+CREATE TABLE IF NOT EXISTS daily_active_seconds (
+    day TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    seconds INTEGER NOT NULL,
+    PRIMARY KEY (day, user_id)
+);
+
+-- Completed registrations each day.
+CREATE TABLE IF NOT EXISTS registrations (
+    day TEXT PRIMARY KEY,
+    count INTEGER NOT NULL
+);
+
+-- How many times each event (see event.rs) happened each day.
+CREATE TABLE IF NOT EXISTS events (
+    event TEXT NOT NULL,
+    day TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (event, day)
+);
+
+-- Websocket connections each host took each day.
+CREATE TABLE IF NOT EXISTS websockets (
+    host TEXT NOT NULL,
+    day TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (host, day)
+);
+
+-- HTTP requests each endpoint (like 'GET /api/games/:id') finished each
+-- day, and how many seconds they took.
+CREATE TABLE IF NOT EXISTS endpoints (
+    endpoint TEXT NOT NULL,
+    day TEXT NOT NULL,
+    count INTEGER NOT NULL,
+    p50_seconds INTEGER NOT NULL,
+    p90_seconds INTEGER NOT NULL,
+    p99_seconds INTEGER NOT NULL,
+    max_seconds INTEGER NOT NULL,
+    PRIMARY KEY (endpoint, day)
+);
+
+-- How many of those requests finished with each status.
+CREATE TABLE IF NOT EXISTS endpoint_statuses (
+    endpoint TEXT NOT NULL,
+    day TEXT NOT NULL,
+    status INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    PRIMARY KEY (endpoint, day, status)
+);
+";
+
+
+/**
+ * Writes everything in report into the SQLite database at path, creating
+ * it if need be. It's all done in one transaction, so anyone reading the
+ * database never sees half a report.
+ */
+pub fn write_sqlite(report: &Report, path: &Path) -> Result<(), Error> {
+    write_report(report, path).map_err(|error| Error::Io(io::Error::other(
+        format!("{}: {}", path.display(), error))))
+}
+
+
+// Columns added since the tables were first made. CREATE TABLE IF NOT
+// EXISTS leaves tables that are already there alone, so databases made
+// before these were added need them added by hand.
+const ADDED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("requests", "end_inferred", "INTEGER NOT NULL DEFAULT 0"),
+    ("convocations", "end_inferred", "INTEGER NOT NULL DEFAULT 0"),
+    ("requests", "version", "INTEGER"),
+    ("requests", "last_seen_index", "INTEGER"),
+    ("requests", "host", "TEXT"),
+    ("requests", "close_code", "INTEGER"),
+    ("requests", "close_reason", "TEXT"),
+];
+
+
+fn add_missing_columns(connection: &Connection) -> rusqlite::Result<()> {
+    for (table, column, definition) in ADDED_COLUMNS {
+        let exists: bool = connection.query_row(
+            "SELECT COUNT(*) > 0 FROM pragma_table_info(?) WHERE name = ?",
+            params![table, column], |row| row.get(0))?;
+        if !exists {
+            connection.execute_batch(&format!(
+                "ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))?;
+        }
+    }
+
+    Ok(())
+}
+
+
+fn write_report(report: &Report, path: &Path) -> rusqlite::Result<()> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    add_missing_columns(&connection)?;
+
+    let transaction = connection.transaction()?;
+    if let Some(requests) = &report.requests {
+        write_requests(&transaction, requests)?;
+    }
+    write_convocations(&transaction, report)?;
+    write_counts(&transaction, report)?;
+    transaction.commit()
+}
+
+
+fn write_requests(transaction: &Transaction, requests: &[Request])
+        -> rusqlite::Result<()> {
+    let mut insert = transaction.prepare("
+        INSERT INTO requests
+            (request_id, game_id, user_id, is_admin, version,
+             last_seen_index, host, started_at, ended_at, end_inferred,
+             close_code, close_reason)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (request_id) DO UPDATE SET
+            game_id = excluded.game_id,
+            user_id = excluded.user_id,
+            is_admin = excluded.is_admin,
+            version = excluded.version,
+            last_seen_index = excluded.last_seen_index,
+            host = excluded.host,
+            started_at = excluded.started_at,
+            ended_at = excluded.ended_at,
+            end_inferred = excluded.end_inferred,
+            close_code = excluded.close_code,
+            close_reason = excluded.close_reason")?;
+    for request in requests {
+        let close = request.close.as_ref();
+        insert.execute(params![
+            request.request_id.to_string(), request.game_id as i64,
+            request.user_id.to_string(), request.is_admin,
+            request.query.version, request.query.last_seen_index,
+            request.host, request.start.to_rfc3339(),
+            request.end.to_rfc3339(), request.end_inferred,
+            close.map(|close| close.code), close.map(|close| &close.reason)])?;
+    }
+
+    let mut insert = transaction.prepare("
+        INSERT INTO presences
+            (game_id, user_id, is_admin, started_at, ended_at)
+            VALUES (?, ?, ?, ?, ?)
+        ON CONFLICT (game_id, user_id, is_admin, started_at) DO UPDATE SET
+            ended_at = excluded.ended_at")?;
+    let presences = collect_presences(requests.iter().cloned());
+    for presence in presences.values().flatten() {
+        for span in &presence.spet {
+            insert.execute(params![
+                presence.game_id as i64, presence.user_id.to_string(),
+                presence.is_admin, span.start().to_rfc3339(),
+                span.end().to_rfc3339()])?;
+        }
+    }
+
+    Ok(())
+}
+
+
+fn write_convocations(transaction: &Transaction, report: &Report)
+        -> rusqlite::Result<()> {
+    let mut insert = transaction.prepare("
+        INSERT INTO convocations
+            (game_id, started_at, ended_at, day, provisional, end_inferred)
+            VALUES (?, ?, ?, ?, ?, ?)
+        ON CONFLICT (game_id, started_at) DO UPDATE SET
+            ended_at = excluded.ended_at,
+            day = excluded.day,
+            provisional = excluded.provisional,
+            end_inferred = excluded.end_inferred")?;
+
+    // Who's in a convocation can change while it's provisional, so
+    // participants are replaced rather than upserted.
+    let mut forget = transaction.prepare("
+        DELETE FROM convocation_participants
+            WHERE game_id = ? AND started_at = ?")?;
+    let mut participant = transaction.prepare("
+        INSERT INTO convocation_participants
+            (game_id, started_at, user_id, is_admin)
+            VALUES (?, ?, ?, ?)")?;
+
+    for (day, convocations) in report.convocations.iter().flatten() {
+        for convocation in convocations {
+            let game_id = convocation.game_id as i64;
+            let started_at = convocation.during.start().to_rfc3339();
+            insert.execute(params![
+                game_id, started_at, convocation.during.end().to_rfc3339(),
+                day, convocation.provisional, convocation.end_inferred])?;
+
+            forget.execute(params![game_id, started_at])?;
+            let admins = convocation.admins.iter().map(|user_id| (user_id, true));
+            let players =
+                convocation.players.iter().map(|user_id| (user_id, false));
+            for (user_id, is_admin) in admins.chain(players) {
+                participant.execute(params![
+                    game_id, started_at, user_id.to_string(), is_admin])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+// Active users' days aren't zero-padded, unlike everywhere else
+fn normalize_day(day: &str) -> String {
+    match NaiveDate::parse_from_str(day, "%Y-%m-%d") {
+        Ok(date) => date.format("%Y-%m-%d").to_string(),
+        Err(_) => day.to_string(),
+    }
+}
+
+
+fn write_counts(transaction: &Transaction, report: &Report)
+        -> rusqlite::Result<()> {
+    let mut insert = transaction.prepare("
+        INSERT INTO daily_active_seconds (day, user_id, seconds)
+            VALUES (?, ?, ?)
+        ON CONFLICT (day, user_id) DO UPDATE SET seconds = excluded.seconds")?;
+    for (day, users) in report.active_users.iter().flatten() {
+        for (user_id, seconds) in users {
+            insert.execute(params![
+                normalize_day(day), user_id.to_string(), seconds])?;
+        }
+    }
+
+    let mut insert = transaction.prepare("
+        INSERT INTO registrations (day, count) VALUES (?, ?)
+        ON CONFLICT (day) DO UPDATE SET count = excluded.count")?;
+    for (day, count) in report.registrations.iter().flatten() {
+        insert.execute(params![day.to_string(), *count as i64])?;
+    }
+
+    let mut insert = transaction.prepare("
+        INSERT INTO events (event, day, count) VALUES (?, ?, ?)
+        ON CONFLICT (event, day) DO UPDATE SET count = excluded.count")?;
+    for (event, days) in report.event_counts.iter().flatten() {
+        for (day, count) in days {
+            insert.execute(params![event, day.to_string(), *count as i64])?;
+        }
+    }
+
+    let mut insert = transaction.prepare("
+        INSERT INTO websockets (host, day, count) VALUES (?, ?, ?)
+        ON CONFLICT (host, day) DO UPDATE SET count = excluded.count")?;
+    for (host, days) in report.websockets.iter().flatten() {
+        for (day, count) in days {
+            insert.execute(params![host, day.to_string(), *count as i64])?;
+        }
+    }
+
+    let mut insert = transaction.prepare("
+        INSERT INTO endpoints
+            (endpoint, day, count, p50_seconds, p90_seconds, p99_seconds,
+             max_seconds)
+            VALUES (?, ?, ?, ?, ?, ?, ?)
+        ON CONFLICT (endpoint, day) DO UPDATE SET
+            count = excluded.count,
+            p50_seconds = excluded.p50_seconds,
+            p90_seconds = excluded.p90_seconds,
+            p99_seconds = excluded.p99_seconds,
+            max_seconds = excluded.max_seconds")?;
+    let mut insert_status = transaction.prepare("
+        INSERT INTO endpoint_statuses (endpoint, day, status, count)
+            VALUES (?, ?, ?, ?)
+        ON CONFLICT (endpoint, day, status) DO UPDATE SET
+            count = excluded.count")?;
+    for (endpoint, days) in report.endpoints.iter().flatten() {
+        for (day, stats) in days {
+            let latencies = &stats.latency_seconds;
+            insert.execute(params![
+                endpoint, day.to_string(), stats.count as i64, latencies.p50,
+                latencies.p90, latencies.p99, latencies.max])?;
+            for (status, count) in &stats.statuses {
+                insert_status.execute(params![
+                    endpoint, day.to_string(), status, *count as i64])?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rusqlite::Connection;
+
+    use shmeppy_log::lex::locate_parts;
+    use crate::analysis::{Analysis, Pipeline};
+    use crate::options::Options;
+    use crate::sqlite::write_sqlite;
+
+    fn lines(end: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (uuid, account_id, is_admin) in [
+                ("157edc79", "a1", true),
+                ("257edc79", "b2", false),
+                ("357edc79", "c3", false)].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Started websocket: GET /game-socket/5 1.1", uuid));
+            lines.push(format!(r#"shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:00:00 AM] Client added to client DB: {{"gameId":5,"clientId":1,"account":{{"accountId":"{}","displayName":"Someone"}},"isAdmin":{}}}"#, uuid, account_id, is_admin));
+            lines.push(format!("shmeppy-1 shmeppy-app: ({}-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 {}] Finished websocket: 1000 ''", uuid, end));
+        }
+        for minute in [10, 30, 50, 70].iter() {
+            lines.push(format!("shmeppy-1 shmeppy-app: (157edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 {}:{:02}:00 AM] Committed 1 operation(s).", 1 + minute / 60, minute % 60));
+        }
+        lines
+    }
+
+    fn count(connection: &Connection, table: &str) -> i64 {
+        connection.query_row(&format!("SELECT COUNT(*) FROM {}", table), [],
+                             |row| row.get(0)).unwrap()
+    }
+
+    #[test]
+    fn upserts() {
+        let path = std::env::temp_dir().join(format!(
+            "fast-convoker-sqlite-{}.db", std::process::id()));
+        let _ = fs::remove_file(&path);
+        let options = Options {
+            sqlite: Some(path.clone()),
+            ..Options::default()
+        };
+
+        // The second time around everyone stayed a bit longer
+        for end in ["2:30:00 AM", "3:00:00 AM"].iter() {
+            let mut pipeline = Pipeline::new(&Analysis::ALL, &options);
+            for line in lines(end) {
+                pipeline.update(&locate_parts(line.as_bytes()).unwrap());
+            }
+            write_sqlite(&pipeline.finish(), &path).unwrap();
+        }
+
+        let connection = Connection::open(&path).unwrap();
+        assert_eq!(count(&connection, "requests"), 3);
+        assert_eq!(count(&connection, "presences"), 3);
+        assert_eq!(count(&connection, "convocations"), 1);
+        assert_eq!(count(&connection, "convocation_participants"), 3);
+
+        let (ended_at, close_code): (String, i64) = connection.query_row(
+            "SELECT ended_at, close_code FROM requests
+                WHERE user_id = 'account_id:161'",
+            [], |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(ended_at, "2020-05-26T03:00:00+00:00");
+        assert_eq!(close_code, 1000);
+
+        let (day, seconds): (String, i64) = connection.query_row(
+            "SELECT day, seconds FROM daily_active_seconds", [],
+            |row| Ok((row.get(0)?, row.get(1)?))).unwrap();
+        assert_eq!(day, "2020-05-26");
+        assert!(seconds > 0);
+
+        let admins: i64 = connection.query_row(
+            "SELECT COUNT(*) FROM convocation_participants WHERE is_admin",
+            [], |row| row.get(0)).unwrap();
+        assert_eq!(admins, 1);
+
+        drop(connection);
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::Stage;
+use shmeppy_log::lex::Parts;
+use shmeppy_log::parse::skip_prefix;
+use shmeppy_log::zones::Zones;
+
+
+/**
+ * Counts the websockets opened on each host on each day.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebsocketCollector {
+    // Maps hosts to dates to number of websockets opened. There's only ever
+    // a handful of hosts, so the String allocation only happens a handful of
+    // times.
+    counts: BTreeMap<String, BTreeMap<DateString, usize>>,
+    #[serde(skip)]
+    zones: Zones,
+}
+
+
+impl WebsocketCollector {
+    pub fn new() -> WebsocketCollector {
+        WebsocketCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> WebsocketCollector {
+        WebsocketCollector {
+            counts: BTreeMap::new(),
+            zones,
+        }
+    }
+
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
+        if skip_prefix(b"Started websocket: ", &parts.message).is_none() {
+            return Ok(());
+        }
+        let date = self.zones.day(parts.timestamp)
+            .ok_or(Stage::ParseTimestamp)?;
+
+        let host = match std::str::from_utf8(parts.host) {
+            Ok(host) => host,
+            Err(_) => return Ok(()),
+        };
+        let by_date = match self.counts.get_mut(host) {
+            Some(by_date) => by_date,
+            None => self.counts.entry(host.to_string()).or_default(),
+        };
+        *by_date.entry(date).or_insert(0) += 1;
+
+        Ok(())
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: WebsocketCollector) {
+        for (key, later_counts) in later.counts {
+            let counts = self.counts.entry(key).or_default();
+            for (date, count) in later_counts {
+                *counts.entry(date).or_insert(0) += count;
+            }
+        }
+    }
+
+    pub fn into_counts(self) -> BTreeMap<String, BTreeMap<DateString, usize>> {
+        self.counts
+    }
+}
+
+
+impl Default for WebsocketCollector {
+    fn default() -> WebsocketCollector {
+        WebsocketCollector::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use shmeppy_log::datestring::DateString;
+    use shmeppy_log::lex::locate_parts;
+    use crate::websocket::WebsocketCollector;
+
+    #[test]
+    fn per_host() {
+        let lines: Vec<&[u8]> = vec![
+            b"shmeppy-0 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:47 AM] Started websocket: GET /game-socket/92829",
+            b"shmeppy-1 shmeppy-app: (457edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:33:48 AM] Started websocket: GET /game-socket/92829?version=1",
+            b"shmeppy-1 shmeppy-app: (557edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:33:48 AM] Started websocket: GET /game-socket/1",
+            b"shmeppy-1 shmeppy-app: (557edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/27/2020 1:33:49 AM] Finished websocket: 1000 ''",
+        ];
+
+        let mut collector = WebsocketCollector::new();
+        for line in lines {
+            collector.update(&locate_parts(line).unwrap()).unwrap();
+        }
+
+        let counts = collector.into_counts();
+        assert_eq!(
+            counts["shmeppy-0"].iter().collect::<Vec<_>>(),
+            vec![(&DateString(*b"2020-05-26"), &1)]);
+        assert_eq!(
+            counts["shmeppy-1"].iter().collect::<Vec<_>>(),
+            vec![(&DateString(*b"2020-05-26"), &1),
+                 (&DateString(*b"2020-05-27"), &1)]);
+    }
+}
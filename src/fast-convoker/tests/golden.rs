@@ -0,0 +1,131 @@
+// Runs the binaries over a small log and checks they write exactly what
+// they wrote last time. If a change to the output is on purpose, run these
+// with UPDATE_GOLDEN=1 (and --test-threads 1, since several of them write
+// the same files) to write the new output over the old, and look over the
+// diff before committing it. fast-log-utils checks its binaries against
+// the registrations and event counts here too.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use fast_convoker::analysis::Analysis;
+
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+
+fn log_path() -> PathBuf {
+    golden_dir().join("shmeppy.log")
+}
+
+
+fn scratch_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "fast-convoker-golden-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+
+fn run(binary: &str, args: &[&str]) -> Vec<u8> {
+    let output = Command::new(binary).args(args).output().unwrap();
+    assert!(output.status.success(), "{} {:?} failed: {}", binary, args,
+            String::from_utf8_lossy(&output.stderr));
+    output.stdout
+}
+
+
+fn assert_golden(name: &str, written: &[u8]) {
+    let path = golden_dir().join(name);
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        fs::write(&path, written).unwrap();
+    }
+
+    let expected = fs::read(&path).unwrap();
+    assert!(written == expected.as_slice(),
+            "{} doesn't match what was written before", name);
+}
+
+
+#[test]
+fn metrics_all() {
+    let log = log_path();
+    let log = log.to_str().unwrap();
+
+    // Every run over the same logs should come out byte for byte the same,
+    // however many jobs they're read with
+    let mut runs = Vec::new();
+    for jobs in &["1", "4"] {
+        let dir = scratch_dir(&format!("all-{}", jobs));
+        run(env!("CARGO_BIN_EXE_shmeppy-metrics"),
+            &["--jobs", jobs, "all", dir.to_str().unwrap(), log]);
+        runs.push(dir);
+    }
+
+    // Checked against each other first, since UPDATE_GOLDEN would otherwise
+    // have each run write over the last one's files and pass regardless
+    let listing = |dir: &Path| {
+        let mut names: Vec<_> = fs::read_dir(dir).unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        names.sort();
+        names
+    };
+    assert_eq!(listing(&runs[0]), listing(&runs[1]));
+    for name in listing(&runs[0]) {
+        assert!(fs::read(runs[0].join(&name)).unwrap() ==
+                    fs::read(runs[1].join(&name)).unwrap(),
+                "{:?} differs between --jobs 1 and --jobs 4", name);
+    }
+
+    for analysis in Analysis::ALL.iter() {
+        let name = analysis.file_name();
+        assert_golden(&name, &fs::read(runs[0].join(&name)).unwrap());
+    }
+
+    for dir in &runs {
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
+
+
+#[test]
+fn single_analyses() {
+    let log = log_path();
+    let log = log.to_str().unwrap();
+
+    // These are the same analyses shmeppy-metrics all writes, so they're
+    // checked against the same files
+    assert_golden("convocations.json",
+                  &run(env!("CARGO_BIN_EXE_fast-convoker"), &[log]));
+    assert_golden("active-users.json",
+                  &run(env!("CARGO_BIN_EXE_active-users"), &[log]));
+
+    // The log ends on the 26th, so that's what --as-of defaults to
+    assert_golden("active-users.json", &run(
+        env!("CARGO_BIN_EXE_active-users"), &["--as-of", "2020-05-26", log]));
+}
+
+
+#[test]
+fn as_of() {
+    let log = log_path();
+    let log = log.to_str().unwrap();
+    let earlier = run(env!("CARGO_BIN_EXE_active-users"),
+                      &["--as-of", "2020-05-25", log]);
+    let later = run(env!("CARGO_BIN_EXE_active-users"),
+                    &["--as-of", "2020-06-30", log]);
+
+    let days = |written: &[u8]| -> Vec<String> {
+        let active_users: serde_json::Map<String, serde_json::Value> =
+            serde_json::from_slice(written).unwrap();
+        active_users.keys().cloned().collect()
+    };
+    assert!(!days(&earlier).contains(&"2020-5-26".to_string()));
+    assert!(days(&later).contains(&"2020-6-30".to_string()));
+    assert!(!days(&later).contains(&"2020-7-1".to_string()));
+}
@@ -0,0 +1,151 @@
+use std::collections::BTreeMap;
+use std::process::exit;
+
+use shmeppy_log::daterange::parse_date;
+use shmeppy_log::datestring::DateString;
+use shmeppy_log::error::{Error, Stage};
+use shmeppy_log::feed::{Feed, Feeder};
+use shmeppy_log::filter::Filter;
+use shmeppy_log::input::collect_sources;
+use shmeppy_log::lex::Parts;
+use shmeppy_log::zones::{Zones, parse_zone};
+
+
+/**
+ * The command line options these binaries understand.
+ */
+#[derive(Debug, Default)]
+pub struct Options {
+    // Stop at the first bad line or unreadable file, rather than skipping
+    // it and carrying on
+    pub strict: bool,
+
+    // Lines the filter doesn't allow are skipped before anything else is
+    // done with them
+    pub filter: Filter,
+
+    // Lines are counted on the day they happened in zones.report
+    pub zones: Zones,
+
+    // The day to treat as today. Nothing after it is counted.
+    pub as_of: Option<DateString>,
+
+    pub positional: Vec<String>,
+}
+
+
+pub const USAGE: &str = "\
+options:
+  --min-level LEVEL  ignore lines below LEVEL (ie: INFO to drop DEBUG)
+  --host HOST        only look at lines from HOST (can be repeated)
+  --since DATE       only look at lines from DATE (YYYY-MM-DD, in the
+                     logs' timezone) on
+  --until DATE       only look at lines up through DATE
+  --as-of DATE       report as if it were DATE, leaving out anything
+                     after it (default the day of the last line read)
+  --log-tz ZONE      the timezone the logs were written in (default UTC)
+  --report-tz ZONE   the timezone to bucket days by (default UTC)
+  --strict           stop at the first bad line or unreadable file
+  --lenient          skip bad lines and unreadable files (the default)";
+
+
+impl Options {
+    pub fn parse(args: impl IntoIterator<Item = String>)
+            -> Result<Options, String> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            if options.filter.parse_flag(&arg, &mut args)? {
+                continue;
+            }
+
+            let mut value = |flag: &str| args.next().ok_or_else(
+                || format!("{} needs a value", flag));
+
+            match arg.as_str() {
+                "--strict" => options.strict = true,
+                "--lenient" => options.strict = false,
+                "--as-of" => options.as_of = Some(parse_date(&value(&arg)?)?),
+                "--log-tz" =>
+                    options.zones.log = parse_zone(&value(&arg)?)?,
+                "--report-tz" =>
+                    options.zones.report = parse_zone(&value(&arg)?)?,
+                _ if arg.starts_with("--") =>
+                    return Err(format!("unknown option {}", arg)),
+                _ => options.positional.push(arg),
+            }
+        }
+
+        options.filter.check()?;
+        Ok(options)
+    }
+
+    /**
+     * Parses this process's arguments, exiting with usage if they don't make
+     * sense.
+     */
+    pub fn from_env(usage: &str) -> Options {
+        match Options::parse(std::env::args().skip(1)) {
+            Ok(options) => options,
+            Err(message) => {
+                eprintln!("{}\n{}\n{}", message, usage, USAGE);
+                exit(1);
+            },
+        }
+    }
+
+    /**
+     * Drops the days after --as-of, if it was given. Without it the last
+     * day is whichever the last line was on, so there's nothing to drop.
+     */
+    pub fn only_through_as_of<T>(&self, by_date: &mut BTreeMap<DateString, T>) {
+        if let Some(as_of) = self.as_of {
+            by_date.retain(|date, _| *date <= as_of);
+        }
+    }
+
+    /**
+     * Calls handle with every line of the logs named on the command line,
+     * already split into its parts. handle gives back the stage it rejected
+     * the line at, if it did.
+     *
+     * In strict mode the first rejected line or unreadable file is returned
+     * as an error. Otherwise they're counted and skipped, and the counts are
+     * reported on stderr at the end.
+     */
+    pub fn for_each_line(
+            &self, handle: impl FnMut(&Parts) -> Result<(), Stage>)
+            -> Result<(), Error> {
+        let mut lines = Lines {
+            feeder: Feeder::new(self.filter.clone(), self.strict),
+            handle,
+        };
+        for source in collect_sources(self.positional.iter().cloned())? {
+            lines.feed(&source)?;
+        }
+
+        if !lines.feeder.skipped.is_empty() {
+            eprintln!("{}", lines.feeder.skipped);
+        }
+
+        Ok(())
+    }
+}
+
+
+// Hands the lines for_each_line reads to its handle
+struct Lines<F> {
+    feeder: Feeder,
+    handle: F,
+}
+
+
+impl<F: FnMut(&Parts) -> Result<(), Stage>> Feed for Lines<F> {
+    fn feeder(&mut self) -> &mut Feeder {
+        &mut self.feeder
+    }
+
+    fn handle(&mut self, parts: &Parts) -> Option<Stage> {
+        (self.handle)(parts).err()
+    }
+}
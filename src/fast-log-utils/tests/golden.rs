@@ -0,0 +1,61 @@
+// Runs the binaries over the same small log fast-convoker's golden tests
+// use, and checks they count the same things shmeppy-metrics does. The
+// files are fast-convoker's, so they're only ever updated from there.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+
+fn golden_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("..").join("fast-convoker").join("tests").join("golden")
+}
+
+
+fn run(binary: &str, args: &[&str]) -> Vec<u8> {
+    let log = golden_dir().join("shmeppy.log");
+    let output = Command::new(binary).args(args).arg(log).output().unwrap();
+    assert!(output.status.success(), "{} {:?} failed: {}", binary, args,
+            String::from_utf8_lossy(&output.stderr));
+
+    // These print a newline after their output, which the files don't have
+    let mut written = output.stdout;
+    assert_eq!(written.pop(), Some(b'\n'));
+    written
+}
+
+
+fn assert_golden(name: &str, written: &[u8]) {
+    let expected = fs::read(golden_dir().join(name)).unwrap();
+    assert!(written == expected.as_slice(),
+            "{} doesn't match what shmeppy-metrics wrote", name);
+}
+
+
+#[test]
+fn same_as_metrics() {
+    assert_golden("event-counts.json",
+                  &run(env!("CARGO_BIN_EXE_count-events"), &[]));
+    assert_golden("registrations.json",
+                  &run(env!("CARGO_BIN_EXE_count-registrations"), &[]));
+
+    // The log ends on the 26th, so that's what --as-of defaults to
+    assert_golden("event-counts.json", &run(
+        env!("CARGO_BIN_EXE_count-events"), &["--as-of", "2020-05-26"]));
+    assert_golden("registrations.json", &run(
+        env!("CARGO_BIN_EXE_count-registrations"), &["--as-of", "2020-05-26"]));
+}
+
+
+#[test]
+fn as_of() {
+    // Everything in the log happened on the 26th, so as of the day before
+    // there's nothing to count
+    assert_eq!(run(env!("CARGO_BIN_EXE_count-registrations"),
+                   &["--as-of", "2020-05-25"]),
+               b"{}");
+    assert_eq!(run(env!("CARGO_BIN_EXE_count-events"),
+                   &["--as-of", "2020-05-25"]),
+               br#"{"login":{},"pend_registration":{},"reset_password_other":{},"update_pended_registration":{}}"#.as_ref());
+}
@@ -11,7 +11,8 @@ function main() {
         fetch("data/active-users.json?ham")
             .then(function(r) {
                 return r.json()
-            }),
+            })
+            .then(expandActiveUsers),
         fetch("data/event-counts.json")
             .then(function(r) {
                 return r.json()
@@ -277,6 +278,24 @@ function drawRegistrations(element, registrations) {
 }
 
 
+// Active users written with --compact-active-users list each user once and
+// refer to them by index, this puts them back into {day: {userId: seconds}}
+function expandActiveUsers(activeUsers) {
+    if (!Array.isArray(activeUsers.users)) {
+        return activeUsers;
+    }
+
+    const usersByDay = {};
+    for (const [day, pairs] of Object.entries(activeUsers.days)) {
+        usersByDay[day] = {};
+        for (const [index, seconds] of pairs) {
+            usersByDay[day][activeUsers.users[index]] = seconds;
+        }
+    }
+    return usersByDay;
+}
+
+
 function drawActiveUsersWindow({element, usersByDay, daysInWindow, buckets}) {
     const rows = [[
         {label: "Month", type: "date"},
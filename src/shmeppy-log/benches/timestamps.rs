@@ -0,0 +1,90 @@
+// Compares parse_timestamp against the chrono based parser it replaced.
+//
+// Run with `cargo bench --bench timestamps`. By default this generates a
+// slice of log that looks like a busy evening on the server. Set
+// SHMEPPY_BENCH_LOG to a (plain or gzipped) log file to use real logs
+// instead.
+
+use std::io::BufRead;
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+
+use shmeppy_log::input::Source;
+use shmeppy_log::lex::Lexer;
+use shmeppy_log::parse::{parse_timestamp, parse_timestamp_slow};
+
+
+// Roughly the mix of lines we see in practice: lots of operations being
+// committed, with websockets coming and going around them.
+fn generate_log() -> Vec<Vec<u8>> {
+    let mut lines = Vec::new();
+    for i in 0..200_000u32 {
+        let seconds = i / 10;
+        let timestamp = format!(
+            "5/{}/2020 {}:{:02}:{:02} {}",
+            26 + seconds / 43200,
+            (seconds / 3600) % 12 + 1,
+            (seconds / 60) % 60,
+            seconds % 60,
+            if (seconds / 43200) % 2 == 0 { "AM" } else { "PM" });
+        let message = match i % 10 {
+            0 => "Started websocket: GET /game-socket/302078290?version=1",
+            9 => "Finished websocket: 1001 ''",
+            _ => "Committed 1 operation(s).",
+        };
+        lines.push(format!(
+            "shmeppy-1 shmeppy-app: ({:08x}-03fe-4787-910a-d2f14302348c) \
+             [INFO - {}] {}",
+            i / 100, timestamp, message).into_bytes());
+    }
+
+    lines
+}
+
+
+fn read_log(path: &str) -> Vec<Vec<u8>> {
+    Source::File(path.into()).open().unwrap().split(b'\n')
+        .map(|line| line.unwrap())
+        .collect()
+}
+
+
+// Runs parse over every timestamp a few times, giving back the fastest run
+fn time(timestamps: &[&[u8]],
+        parse: impl Fn(&[u8]) -> Option<DateTime<Utc>>) -> Duration {
+    (0..5).map(|_| {
+        let start = Instant::now();
+        let parsed = timestamps.iter()
+            .filter(|raw| parse(raw).is_some())
+            .count();
+        let elapsed = start.elapsed();
+        assert!(parsed > 0);
+        elapsed
+    }).min().unwrap()
+}
+
+
+fn main() {
+    let lines = match std::env::var("SHMEPPY_BENCH_LOG") {
+        Ok(path) => read_log(&path),
+        Err(_) => generate_log(),
+    };
+
+    let mut lexer = Lexer::new();
+    let timestamps: Vec<&[u8]> = lines.iter()
+        .filter_map(|line| lexer.locate_parts(line))
+        .map(|parts| parts.timestamp)
+        .collect();
+
+    let slow = time(&timestamps, parse_timestamp_slow);
+    let fast = time(&timestamps, parse_timestamp);
+
+    let per_second = |elapsed: Duration|
+        timestamps.len() as f64 / elapsed.as_secs_f64();
+    println!("{} timestamps", timestamps.len());
+    println!("chrono:      {:>12.0} per second", per_second(slow));
+    println!("hand-rolled: {:>12.0} per second", per_second(fast));
+    println!("speedup:     {:>12.1}x",
+             slow.as_secs_f64() / fast.as_secs_f64());
+}
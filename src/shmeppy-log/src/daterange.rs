@@ -0,0 +1,154 @@
+use chrono::{Date, DateTime, NaiveDate, Utc};
+use chrono::offset::TimeZone;
+use chrono_tz::Tz;
+
+use crate::datestring::DateString;
+use crate::zones::start_of_day;
+
+
+/**
+ * The days of logs to look at, from --since through --until (both
+ * inclusive). Either end can be left open.
+ *
+ * Lines are picked by the date in their timestamp, so the days are in
+ * whatever timezone the logs were written in.
+ */
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DateRange {
+    pub since: Option<DateString>,
+    pub until: Option<DateString>,
+}
+
+
+/**
+ * Parses a YYYY-MM-DD date, as given to --since or --until.
+ */
+pub fn parse_date(raw: &str) -> Result<DateString, String> {
+    NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+        .map(|date| DateString::from_date(&date))
+        .map_err(|_| format!("expected a YYYY-MM-DD date, not {}", raw))
+}
+
+
+impl DateRange {
+    pub fn is_everything(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+
+    pub fn contains(&self, date: DateString) -> bool {
+        self.since.is_none_or(|since| since <= date) &&
+            self.until.is_none_or(|until| date <= until)
+    }
+
+    /**
+     * Whether the line with this raw timestamp comes after the range.
+     */
+    pub fn is_after_timestamp(&self, raw: &[u8]) -> bool {
+        match (self.until, DateString::from_timestamp(raw)) {
+            (Some(until), Some(date)) => date > until,
+            _ => false,
+        }
+    }
+
+    /**
+     * Whether the line with this raw timestamp is in the range. This only
+     * looks at the date, and is cheap enough to do before anything else.
+     *
+     * Timestamps we can't get a date out of are let through, so that
+     * whatever parses them later gets to reject them properly.
+     */
+    pub fn contains_timestamp(&self, raw: &[u8]) -> bool {
+        if self.is_everything() {
+            return true;
+        }
+
+        // DateStrings sort the same way their dates do, so there's no
+        // need to parse them
+        match DateString::from_timestamp(raw) {
+            Some(date) => self.contains(date),
+            None => true,
+        }
+    }
+
+    /**
+     * The moment the range stops in tz, if it does. That's the very start
+     * of the day after --until.
+     */
+    pub fn end_in(&self, tz: Tz) -> Option<DateTime<Utc>> {
+        let until = self.until?.to_date()?;
+        Some(start_of_day(&tz.from_utc_date(&until.succ())))
+    }
+
+    /**
+     * The days in tz to report on, from since (or first) through until (or
+     * last), whichever are narrower.
+     */
+    pub fn clamp(&self, first: Date<Tz>, last: Date<Tz>) -> (Date<Tz>, Date<Tz>) {
+        let tz = first.timezone();
+        let first = match self.since.and_then(|since| since.to_date()) {
+            Some(since) if since > first.naive_local() => tz.from_utc_date(&since),
+            _ => first,
+        };
+        let last = match self.until.and_then(|until| until.to_date()) {
+            Some(until) if until < last.naive_local() => tz.from_utc_date(&until),
+            _ => last,
+        };
+        (first, last)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono::offset::TimeZone;
+    use chrono_tz::America::Los_Angeles;
+
+    use crate::datestring::DateString;
+    use crate::daterange::{DateRange, parse_date};
+
+    fn range(since: Option<&str>, until: Option<&str>) -> DateRange {
+        DateRange {
+            since: since.map(|raw| parse_date(raw).unwrap()),
+            until: until.map(|raw| parse_date(raw).unwrap()),
+        }
+    }
+
+    #[test]
+    fn parses() {
+        assert_eq!(parse_date("2020-5-26"), Ok(DateString(*b"2020-05-26")));
+        assert!(parse_date("2020-13-01").is_err());
+        assert!(parse_date("5/26/2020").is_err());
+    }
+
+    #[test]
+    fn timestamps() {
+        let may = range(Some("2020-05-01"), Some("2020-05-31"));
+        assert!(may.contains_timestamp(b"5/1/2020 12:00:00 AM"));
+        assert!(may.contains_timestamp(b"5/31/2020 11:59:59 PM"));
+        assert!(!may.contains_timestamp(b"4/30/2020 11:59:59 PM"));
+        assert!(!may.contains_timestamp(b"6/1/2020 12:00:00 AM"));
+        assert!(may.is_after_timestamp(b"6/1/2020 12:00:00 AM"));
+        assert!(!may.is_after_timestamp(b"4/30/2020 11:59:59 PM"));
+
+        // Left for the parser to complain about
+        assert!(may.contains_timestamp(b"garbage"));
+
+        let open = range(None, Some("2020-05-31"));
+        assert!(open.contains_timestamp(b"1/1/1970 12:00:00 AM"));
+        assert!(DateRange::default().contains_timestamp(b"6/1/2020 12:00:00 AM"));
+    }
+
+    #[test]
+    fn edges() {
+        let may = range(Some("2020-05-01"), Some("2020-05-31"));
+        assert_eq!(may.end_in(Los_Angeles),
+                   Some(Utc.ymd(2020, 6, 1).and_hms(7, 0, 0)));
+        assert_eq!(DateRange::default().end_in(Los_Angeles), None);
+
+        let (first, last) = may.clamp(
+            Los_Angeles.ymd(2018, 1, 1), Los_Angeles.ymd(2020, 5, 15));
+        assert_eq!(first, Los_Angeles.ymd(2020, 5, 1));
+        assert_eq!(last, Los_Angeles.ymd(2020, 5, 15));
+    }
+}
@@ -0,0 +1,144 @@
+use chrono::{Datelike, NaiveDate};
+use memchr::memchr;
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+
+/**
+ * A YYYY-MM-DD date, stored as the ASCII bytes themselves.
+ *
+ * This sorts the same way the date does, so it can key a BTreeMap directly
+ * and be serialized without any formatting.
+ */
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy)]
+pub struct DateString(pub [u8; 10]);
+
+
+impl Serialize for DateString {
+    fn serialize<S: Serializer>(&self, serializer: S)
+            -> Result<S::Ok, S::Error> {
+        // We only ever fill this with ASCII digits and dashes
+        serializer.serialize_str(std::str::from_utf8(&self.0).unwrap())
+    }
+}
+
+
+impl<'de> Deserialize<'de> for DateString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+            -> Result<DateString, D::Error> {
+        let raw = String::deserialize(deserializer)?;
+        let mut buffer = [0; 10];
+        if raw.len() != buffer.len() {
+            return Err(de::Error::invalid_length(raw.len(), &"a YYYY-MM-DD date"));
+        }
+
+        buffer.copy_from_slice(raw.as_bytes());
+        Ok(DateString(buffer))
+    }
+}
+
+
+// Copies a one or two digit number into a two byte, zero-padded buffer
+fn copy_padded(into: &mut [u8], digits: &[u8]) -> Option<()> {
+    if !digits.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+
+    match digits.len() {
+        1 => {
+            into[0] = b'0';
+            into[1] = digits[0];
+        },
+        2 => into.copy_from_slice(digits),
+        _ => return None,
+    }
+
+    Some(())
+}
+
+
+impl DateString {
+    /**
+     * Pulls the date out of a raw timestamp like 4/25/2020 9:55:14 PM.
+     *
+     * This skips parsing the time entirely, and is much cheaper than going
+     * through parse_timestamp when all we want is the day.
+     */
+    pub fn from_timestamp(raw: &[u8]) -> Option<DateString> {
+        let first_slash = memchr(b'/', raw)?;
+        let second_slash = first_slash + 1 + memchr(b'/', &raw[first_slash + 1..])?;
+        let year = raw.get(second_slash + 1..second_slash + 5)?;
+        if !year.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+
+        let mut buffer: [u8; 10] = [b'-'; 10];
+        buffer[..4].copy_from_slice(year);
+        copy_padded(&mut buffer[5..7], &raw[..first_slash])?;
+        copy_padded(&mut buffer[8..], &raw[first_slash + 1..second_slash])?;
+
+        Some(DateString(buffer))
+    }
+
+    /**
+     * Gives back the date, if this is a real one.
+     */
+    pub fn to_date(&self) -> Option<NaiveDate> {
+        NaiveDate::parse_from_str(std::str::from_utf8(&self.0).ok()?, "%Y-%m-%d")
+            .ok()
+    }
+
+    pub fn from_date(date: &impl Datelike) -> DateString {
+        let formatted = format!(
+            "{:04}-{:02}-{:02}", date.year(), date.month(), date.day());
+        let mut buffer: [u8; 10] = [b'-'; 10];
+        buffer.copy_from_slice(&formatted.as_bytes()[..10]);
+        DateString(buffer)
+    }
+}
+
+
+impl std::fmt::Display for DateString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // We only ever fill this with ASCII digits and dashes
+        f.write_str(std::str::from_utf8(&self.0).unwrap())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::datestring::DateString;
+
+    #[test]
+    fn from_timestamp() {
+        assert_eq!(DateString::from_timestamp(b"4/25/2020 9:55:14 PM"),
+                   Some(DateString(*b"2020-04-25")));
+        assert_eq!(DateString::from_timestamp(b"10/1/2018 8:43:45 PM"),
+                   Some(DateString(*b"2018-10-01")));
+        assert_eq!(DateString::from_timestamp(b"12/31/2019 11:59:59 PM"),
+                   Some(DateString(*b"2019-12-31")));
+    }
+
+    #[test]
+    fn malformed() {
+        assert_eq!(DateString::from_timestamp(b""), None);
+        assert_eq!(DateString::from_timestamp(b"4/25"), None);
+        assert_eq!(DateString::from_timestamp(b"4/25/20"), None);
+        assert_eq!(DateString::from_timestamp(b"123/25/2020 9:55:14 PM"), None);
+        assert_eq!(DateString::from_timestamp(b"a/25/2020 9:55:14 PM"), None);
+    }
+
+    #[test]
+    fn from_date() {
+        use chrono::NaiveDate;
+        assert_eq!(DateString::from_date(&NaiveDate::from_ymd(2020, 4, 5)),
+                   DateString(*b"2020-04-05"));
+    }
+
+    #[test]
+    fn serialize() {
+        assert_eq!(serde_json::to_string(&DateString(*b"2020-04-25")).unwrap(),
+                   "\"2020-04-25\"");
+    }
+}
@@ -0,0 +1,107 @@
+use std::fmt;
+use std::io;
+
+use serde::{Deserialize, Serialize};
+
+use crate::input::Source;
+
+
+/**
+ * The step of handling a log line that gave up on it.
+ */
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stage {
+    LocateParts,
+    ParseUuid,
+    ParseTimestamp,
+    ParseMessage,
+}
+
+
+impl fmt::Display for Stage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Stage::LocateParts => "locate_parts",
+            Stage::ParseUuid => "parse_uuid",
+            Stage::ParseTimestamp => "parse_timestamp",
+            Stage::ParseMessage => "parse_message",
+        })
+    }
+}
+
+
+#[derive(Debug)]
+pub enum Error {
+    // Something went wrong that isn't tied to any one log file (ie: we
+    // couldn't list a directory, or write out a report)
+    Io(io::Error),
+
+    // We couldn't read from a log file. Truncated gzip files are the usual
+    // culprit.
+    Read {
+        source: Source,
+        error: io::Error,
+    },
+
+    // A log line we couldn't make sense of. Only an error in strict mode.
+    Rejected {
+        source: Source,
+        line_number: usize,
+        stage: Stage,
+        line: Vec<u8>,
+    },
+
+    Json(serde_json::Error),
+}
+
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::Read { source, error } =>
+                write!(f, "{}: {}", source, error),
+            Error::Rejected { source, line_number, stage, line } =>
+                write!(f, "{}:{}: rejected at {}: {}", source, line_number,
+                       stage, String::from_utf8_lossy(line)),
+            Error::Json(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+
+impl std::error::Error for Error {}
+
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Error {
+        Error::Json(error)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{Error, Stage};
+    use crate::input::Source;
+
+    #[test]
+    fn display() {
+        let error = Error::Rejected {
+            source: Source::File("logs/app.log".into()),
+            line_number: 12,
+            stage: Stage::ParseTimestamp,
+            line: b"garbage".to_vec(),
+        };
+        assert_eq!(error.to_string(),
+                   "logs/app.log:12: rejected at parse_timestamp: garbage");
+    }
+}
@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use serde::de;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::datestring::DateString;
+use crate::error::Stage;
+use crate::lex::Parts;
+use crate::parse::skip_prefix;
+use crate::zones::Zones;
+
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct Event {
+    pub name: &'static str,
+    pub prefix: &'static [u8],
+}
+
+
+pub const EVENTS: [Event; 4] = [
+    Event {
+        name: "login",
+        prefix: b"Started: POST /api/auth/login ",
+    },
+    Event {
+        name: "pend_registration",
+        prefix: b"Started: POST /api/account/register ",
+    },
+    Event {
+        name: "update_pended_registration",
+        prefix: b"Started: PUT /api/account/register/",
+    },
+    Event {
+        name: "reset_password_other",
+        prefix: b"Started: POST /api/account/reset-password ",
+    },
+];
+
+
+/**
+ * Counts how many times each of EVENTS happened on each day.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EventCollector {
+    // Using a nested map here, rather than something more efficient, so
+    // that I don't have to write more serialization functions for serde...
+    #[serde(deserialize_with = "deserialize_counts")]
+    counts: BTreeMap<&'static str, BTreeMap<DateString, usize>>,
+    #[serde(skip)]
+    zones: Zones,
+}
+
+
+// Names come back out of a checkpoint as owned strings, so this swaps them
+// for the matching names in EVENTS.
+fn deserialize_counts<'de, D: Deserializer<'de>>(deserializer: D)
+        -> Result<BTreeMap<&'static str, BTreeMap<DateString, usize>>, D::Error> {
+    let counts: BTreeMap<String, BTreeMap<DateString, usize>> =
+        Deserialize::deserialize(deserializer)?;
+    counts.into_iter().map(|(name, by_date)| {
+        match EVENTS.iter().find(|event| event.name == name) {
+            Some(event) => Ok((event.name, by_date)),
+            None => Err(de::Error::custom(format!("unknown event {}", name))),
+        }
+    }).collect()
+}
+
+
+impl EventCollector {
+    pub fn new() -> EventCollector {
+        EventCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> EventCollector {
+        let mut counts = BTreeMap::new();
+        for event in EVENTS.iter() {
+            counts.insert(event.name, BTreeMap::new());
+        }
+
+        EventCollector { counts, zones }
+    }
+
+    /**
+     * Gives back the stage the line was rejected at, if it's an event we
+     * couldn't put on a day.
+     */
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
+        let event = match EVENTS.iter().find(
+                |event| skip_prefix(event.prefix, &parts.message).is_some()) {
+            Some(event) => event,
+            None => return Ok(()),
+        };
+        let date = self.zones.day(parts.timestamp)
+            .ok_or(Stage::ParseTimestamp)?;
+        if let Some(counts) = self.counts.get_mut(event.name) {
+            *counts.entry(date).or_insert(0) += 1;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: EventCollector) {
+        for (key, later_counts) in later.counts {
+            let counts = self.counts.entry(key).or_default();
+            for (date, count) in later_counts {
+                *counts.entry(date).or_insert(0) += count;
+            }
+        }
+    }
+
+    pub fn into_counts(self) -> BTreeMap<&'static str, BTreeMap<DateString, usize>> {
+        self.counts
+    }
+}
+
+
+impl Default for EventCollector {
+    fn default() -> EventCollector {
+        EventCollector::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::datestring::DateString;
+    use crate::lex::locate_parts;
+    use crate::event::EventCollector;
+
+    #[test]
+    fn standard_use() {
+        let lines: Vec<&[u8]> = vec![
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/auth/login 1.1",
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Finished: 200 'OK'",
+            b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/auth/login 1.1",
+            b"shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/27/2020 3:33:20 PM] Started: PUT /api/account/register/abc 1.1",
+        ];
+
+        let mut collector = EventCollector::new();
+        for line in lines {
+            collector.update(&locate_parts(line).unwrap()).unwrap();
+        }
+
+        let counts = collector.into_counts();
+        assert_eq!(counts.len(), 4);
+        assert_eq!(
+            counts["login"].iter().collect::<Vec<_>>(),
+            vec![(&DateString(*b"2020-05-26"), &2)]);
+        assert_eq!(
+            counts["update_pended_registration"].iter().collect::<Vec<_>>(),
+            vec![(&DateString(*b"2020-05-27"), &1)]);
+        assert!(counts["pend_registration"].is_empty());
+    }
+}
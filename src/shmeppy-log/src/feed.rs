@@ -0,0 +1,304 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::BufRead;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{Error, Stage};
+use crate::filter::Filter;
+use crate::input::Source;
+use crate::lex::{Lexer, Parts};
+
+
+/**
+ * What a lenient Feeder skipped over rather than failing on.
+ */
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Skipped {
+    pub lines: BTreeMap<Stage, usize>,
+
+    // Errors from sources we couldn't read all the way through. These can't
+    // be saved, so anything with any of them shouldn't be checkpointed.
+    #[serde(skip)]
+    pub unreadable: Vec<Error>,
+}
+
+
+impl Skipped {
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty() && self.unreadable.is_empty()
+    }
+
+    pub fn merge(&mut self, later: Skipped) {
+        for (stage, count) in later.lines {
+            *self.lines.entry(stage).or_insert(0) += count;
+        }
+        self.unreadable.extend(later.unreadable);
+    }
+}
+
+
+impl fmt::Display for Skipped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total: usize = self.lines.values().sum();
+        let by_stage: Vec<String> = self.lines.iter()
+            .map(|(stage, count)| format!("{}: {}", stage, count))
+            .collect();
+        write!(f, "skipped {} bad line(s)", total)?;
+        if !by_stage.is_empty() {
+            write!(f, " ({})", by_stage.join(", "))?;
+        }
+
+        for error in &self.unreadable {
+            write!(f, "\nstopped reading {}", error)?;
+        }
+
+        Ok(())
+    }
+}
+
+
+/**
+ * The state every Feed keeps while reading lines, whatever it ends up doing
+ * with them. filter and strict come from the options, so they aren't saved.
+ */
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Feeder {
+    // Lines the filter doesn't allow are dropped before anything else is
+    // done with them
+    #[serde(skip)]
+    pub filter: Filter,
+
+    // Whether a bad line or unreadable file should stop everything, rather
+    // than being skipped and counted in skipped
+    #[serde(skip)]
+    pub strict: bool,
+    pub skipped: Skipped,
+
+    // Whether we've seen any lines after --until, meaning requests that are
+    // still open were cut off by it rather than by the logs running out
+    pub past_until: bool,
+}
+
+
+impl Feeder {
+    pub fn new(filter: Filter, strict: bool) -> Feeder {
+        Feeder { filter, strict, ..Feeder::default() }
+    }
+
+    /**
+     * Folds in a feeder that was fed the lines right after the ones this one
+     * was fed.
+     */
+    pub fn merge(&mut self, later: Feeder) {
+        self.skipped.merge(later.skipped);
+        self.past_until |= later.past_until;
+    }
+}
+
+
+/**
+ * Something that reads log lines. Opening, lexing, filtering and deciding
+ * what to do about bad lines all happens here, so that everything reading
+ * the logs skips (or fails on) the same lines.
+ */
+pub trait Feed {
+    fn feeder(&mut self) -> &mut Feeder;
+
+    /**
+     * Handles a line the filter allowed, giving back the stage it was
+     * rejected at if it was.
+     */
+    fn handle(&mut self, parts: &Parts) -> Option<Stage>;
+
+    /**
+     * Looks over every non-empty line the filter didn't drop before it's
+     * handled (maybe_parts is None if the lexer couldn't make sense of it).
+     * A line rejected here is never handled.
+     *
+     * By default only lines that couldn't be lexed are rejected, leaving the
+     * rest for handle to trip over.
+     */
+    fn check(&mut self, _source: &Source, _line_number: usize, _line: &[u8],
+             maybe_parts: Option<&Parts>) -> Option<Stage> {
+        match maybe_parts {
+            Some(_) => None,
+            None => Some(Stage::LocateParts),
+        }
+    }
+
+    /**
+     * Reads every line out of source. Outside of strict mode this only
+     * fails if the source can't be opened at all.
+     */
+    fn feed(&mut self, source: &Source) -> Result<(), Error> {
+        let reader = source.open().map_err(|error| Error::Read {
+            source: source.clone(),
+            error,
+        })?;
+
+        // Each file gets its own lexer, since the format could change
+        // between files.
+        let mut lexer = Lexer::new();
+        for (i, maybe_line) in reader.split(b'\n').enumerate() {
+            let line = match maybe_line {
+                Ok(line) => line,
+                Err(error) => {
+                    let error = Error::Read { source: source.clone(), error };
+                    if self.feeder().strict {
+                        return Err(error);
+                    }
+
+                    // Usually a truncated gzip file. Whatever we got out of
+                    // it before the error is still good.
+                    self.feeder().skipped.unreadable.push(error);
+                    return Ok(());
+                },
+            };
+            self.feed_line(source, &mut lexer, i + 1, line)?;
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Handles a single line, the same way feed would. Only fails in strict
+     * mode.
+     */
+    fn feed_line(&mut self, source: &Source, lexer: &mut Lexer,
+                 line_number: usize, line: Vec<u8>) -> Result<(), Error> {
+        // Blank lines (ie: the one after the trailing newline) aren't worth
+        // complaining about
+        if line.is_empty() {
+            return Ok(());
+        }
+
+        let maybe_parts = lexer.locate_parts(&line);
+        if let Some(parts) = &maybe_parts {
+            let feeder = self.feeder();
+            if feeder.filter.dates.is_after_timestamp(parts.timestamp) {
+                feeder.past_until = true;
+            }
+            if !feeder.filter.allows(parts) {
+                return Ok(());
+            }
+        }
+
+        let checked =
+            self.check(source, line_number, &line, maybe_parts.as_ref());
+        let rejected = match (checked, &maybe_parts) {
+            (None, Some(parts)) => self.handle(parts),
+            (rejected, _) => rejected,
+        };
+
+        let feeder = self.feeder();
+        match rejected {
+            Some(stage) if feeder.strict => {
+                return Err(Error::Rejected {
+                    source: source.clone(),
+                    line_number,
+                    stage,
+                    line,
+                });
+            },
+            Some(stage) => {
+                *feeder.skipped.lines.entry(stage).or_insert(0) += 1;
+            },
+            None => (),
+        }
+
+        Ok(())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::daterange::parse_date;
+    use crate::error::{Error, Stage};
+    use crate::feed::{Feed, Feeder};
+    use crate::filter::Filter;
+    use crate::input::Source;
+    use crate::lex::{Level, Lexer, Parts};
+
+    // Rejects any line that isn't at INFO, to have something to reject
+    #[derive(Default)]
+    struct InfoOnly {
+        feeder: Feeder,
+        handled: usize,
+    }
+
+    impl Feed for InfoOnly {
+        fn feeder(&mut self) -> &mut Feeder {
+            &mut self.feeder
+        }
+
+        fn handle(&mut self, parts: &Parts) -> Option<Stage> {
+            if parts.level() != Some(Level::Info) {
+                return Some(Stage::ParseMessage);
+            }
+
+            self.handled += 1;
+            None
+        }
+    }
+
+    const LINES: &[&[u8]] = &[
+        b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/auth/login 1.1",
+        b"garbage",
+        b"",
+        b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [DEBUG - 5/26/2020 3:33:19 PM] Something",
+        b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/28/2020 3:33:20 PM] Started: POST /api/auth/login 1.1",
+    ];
+
+    fn feed(feeding: &mut InfoOnly) -> Result<(), Error> {
+        let mut lexer = Lexer::new();
+        for (i, line) in LINES.iter().enumerate() {
+            feeding.feed_line(&Source::Stdin, &mut lexer, i + 1, line.to_vec())?;
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn lenient() {
+        let mut feeding = InfoOnly::default();
+        feed(&mut feeding).unwrap();
+        assert_eq!(feeding.handled, 2);
+        assert_eq!(feeding.feeder.skipped.to_string(),
+                   "skipped 2 bad line(s) (locate_parts: 1, parse_message: 1)");
+    }
+
+    #[test]
+    fn strict() {
+        let mut feeding = InfoOnly {
+            feeder: Feeder::new(Filter::default(), true),
+            ..InfoOnly::default()
+        };
+        match feed(&mut feeding) {
+            Err(Error::Rejected { line_number, stage, .. }) => {
+                assert_eq!(line_number, 2);
+                assert_eq!(stage, Stage::LocateParts);
+            },
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn filtered() {
+        let mut filter = Filter {
+            min_level: Some(Level::Info),
+            ..Filter::default()
+        };
+        filter.dates.until = Some(parse_date("2020-05-27").unwrap());
+        let mut feeding = InfoOnly {
+            feeder: Feeder::new(filter, false),
+            ..InfoOnly::default()
+        };
+        feed(&mut feeding).unwrap();
+        assert_eq!(feeding.handled, 1);
+        assert!(feeding.feeder.past_until);
+        assert_eq!(feeding.feeder.skipped.to_string(),
+                   "skipped 1 bad line(s) (locate_parts: 1)");
+    }
+}
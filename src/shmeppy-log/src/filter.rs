@@ -0,0 +1,143 @@
+use crate::daterange::{DateRange, parse_date};
+use crate::lex::{Level, Parts};
+
+
+/**
+ * Decides which log lines get handed to the collectors at all.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    // Lines below this level are dropped. Lines whose level we don't
+    // recognize are always kept, since we can't say they're noise.
+    pub min_level: Option<Level>,
+
+    // If non-empty, only lines from these hosts are kept
+    pub hosts: Vec<Vec<u8>>,
+
+    // Only lines from these days are kept. Requests that started before
+    // the range are never seen starting, so they're left out entirely, and
+    // ones still going when it ends are cut off there.
+    pub dates: DateRange,
+}
+
+
+fn parse_level(raw: &str) -> Result<Level, String> {
+    Level::from_bytes(raw.to_ascii_uppercase().as_bytes())
+        .ok_or_else(|| format!("unknown level {}", raw))
+}
+
+
+impl Filter {
+    pub fn allows(&self, parts: &Parts) -> bool {
+        if let (Some(min_level), Some(level)) = (self.min_level, parts.level()) {
+            if level < min_level {
+                return false;
+            }
+        }
+
+        (self.hosts.is_empty() ||
+            self.hosts.iter().any(|host| host.as_slice() == parts.host)) &&
+            self.dates.contains_timestamp(parts.timestamp)
+    }
+
+    /**
+     * Handles flag if it's one of --min-level, --host, --since or --until,
+     * taking its value from args. Gives back whether it was.
+     */
+    pub fn parse_flag(&mut self, flag: &str,
+                      args: &mut impl Iterator<Item = String>)
+            -> Result<bool, String> {
+        let mut value = || args.next().ok_or_else(
+            || format!("{} needs a value", flag));
+
+        match flag {
+            "--min-level" => self.min_level = Some(parse_level(&value()?)?),
+            "--host" => self.hosts.push(value()?.into_bytes()),
+            "--since" => self.dates.since = Some(parse_date(&value()?)?),
+            "--until" => self.dates.until = Some(parse_date(&value()?)?),
+            _ => return Ok(false),
+        }
+
+        Ok(true)
+    }
+
+    /**
+     * Checks that the flags parse_flag was given make sense together.
+     */
+    pub fn check(&self) -> Result<(), String> {
+        match (self.dates.since, self.dates.until) {
+            (Some(since), Some(until)) if since > until =>
+                Err(format!("--since {} is after --until {}", since, until)),
+            _ => Ok(()),
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::daterange::DateRange;
+    use crate::datestring::DateString;
+    use crate::filter::Filter;
+    use crate::lex::{Level, locate_parts};
+
+    fn parse(args: &[&str]) -> Result<Filter, String> {
+        let mut filter = Filter::default();
+        let mut args = args.iter().map(|arg| arg.to_string());
+        while let Some(arg) = args.next() {
+            if !filter.parse_flag(&arg, &mut args)? {
+                return Err(format!("unknown option {}", arg));
+            }
+        }
+
+        filter.check()?;
+        Ok(filter)
+    }
+
+    #[test]
+    fn allows() {
+        let debug = locate_parts(b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [DEBUG - 5/26/2020 1:36:23 AM] Waiting for client to complete closing handshake.").unwrap();
+        let info = locate_parts(b"shmeppy-0 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [INFO - 5/26/2020 1:36:23 AM] Finished: 200 'OK'").unwrap();
+
+        let everything = Filter::default();
+        assert!(everything.allows(&debug) && everything.allows(&info));
+
+        let quiet = Filter { min_level: Some(Level::Info), ..Filter::default() };
+        assert!(!quiet.allows(&debug) && quiet.allows(&info));
+
+        let one_host = Filter {
+            hosts: vec![b"shmeppy-1".to_vec()],
+            ..Filter::default()
+        };
+        assert!(one_host.allows(&debug) && !one_host.allows(&info));
+
+        let later = Filter {
+            dates: DateRange {
+                since: Some(DateString(*b"2020-05-27")),
+                until: None,
+            },
+            ..Filter::default()
+        };
+        assert!(!later.allows(&debug) && !later.allows(&info));
+    }
+
+    #[test]
+    fn flags() {
+        let filter = parse(&[
+            "--min-level", "info", "--host", "shmeppy-1", "--host",
+            "shmeppy-2", "--since", "2020-05-01", "--until", "2020-5-31"])
+            .unwrap();
+        assert_eq!(filter.min_level, Some(Level::Info));
+        assert_eq!(filter.hosts,
+                   vec![b"shmeppy-1".to_vec(), b"shmeppy-2".to_vec()]);
+        assert_eq!(filter.dates.since.unwrap().to_string(), "2020-05-01");
+        assert_eq!(filter.dates.until.unwrap().to_string(), "2020-05-31");
+
+        assert!(parse(&["--min-level"]).is_err());
+        assert!(parse(&["--min-level", "loud"]).is_err());
+        assert!(parse(&["--since", "last week"]).is_err());
+        assert!(parse(&["--since", "2020-06-01", "--until", "2020-05-31"])
+            .is_err());
+        assert!(parse(&["--strict"]).is_err());
+    }
+}
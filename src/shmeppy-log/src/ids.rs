@@ -0,0 +1,89 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, Deserialize, Deserializer};
+use serde::ser::{Serialize, Serializer};
+
+use crate::parse::UUID;
+
+
+pub type GameId = u64;
+pub type AccountId = u64;
+
+
+/**
+ * Who made a request, as best we can tell.
+ */
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Ord, PartialOrd)]
+pub enum UserId {
+    AnalyticsId(UUID),
+    AccountId(AccountId),
+    Anonymous,
+}
+
+
+impl fmt::Display for UserId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use UserId::*;
+        match self {
+            AnalyticsId(UUID(uuid)) => write!(f, "analytics_id:{}", uuid),
+            AccountId(id) => write!(f, "account_id:{}", id),
+            Anonymous => write!(f, "anonymous"),
+        }
+    }
+}
+
+
+impl Serialize for UserId {
+    fn serialize<S: Serializer>(&self, serializer: S)
+            -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+
+impl FromStr for UserId {
+    type Err = String;
+
+    // The inverse of Display
+    fn from_str(raw: &str) -> Result<UserId, String> {
+        let invalid = || format!("invalid user id {}", raw);
+        if raw == "anonymous" {
+            Ok(UserId::Anonymous)
+        } else if let Some(uuid) = raw.strip_prefix("analytics_id:") {
+            uuid.parse().map(|uuid| UserId::AnalyticsId(UUID(uuid)))
+                .map_err(|_| invalid())
+        } else if let Some(id) = raw.strip_prefix("account_id:") {
+            id.parse().map(UserId::AccountId).map_err(|_| invalid())
+        } else {
+            Err(invalid())
+        }
+    }
+}
+
+
+impl<'de> Deserialize<'de> for UserId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D)
+            -> Result<UserId, D::Error> {
+        String::deserialize(deserializer)?.parse().map_err(de::Error::custom)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::ids::UserId;
+    use crate::parse::UUID;
+
+    #[test]
+    fn round_trips() {
+        for user_id in [UserId::AnalyticsId(UUID(u128::MAX)),
+                        UserId::AccountId(0xc7d585cd803aafa5),
+                        UserId::Anonymous].iter() {
+            assert_eq!(user_id.to_string().parse(), Ok(*user_id));
+        }
+
+        assert!("account_id:".parse::<UserId>().is_err());
+        assert!("someone".parse::<UserId>().is_err());
+    }
+}
@@ -0,0 +1,264 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, stdin, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use flate2::read::MultiGzDecoder;
+
+use crate::lex::Lexer;
+use crate::parse::parse_naive_timestamp;
+
+
+// Every gzip stream starts with these two bytes
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+
+/**
+ * Somewhere log lines can be read from.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Stdin,
+    File(PathBuf),
+}
+
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Stdin => write!(f, "<stdin>"),
+            Source::File(path) => write!(f, "{}", path.display()),
+        }
+    }
+}
+
+
+impl Source {
+    /**
+     * Opens the source for reading, transparently decompressing it if it's
+     * gzipped.
+     *
+     * We sniff for the gzip magic bytes rather than trusting the extension,
+     * so plain logs named on the command line (ie: the live app.log, or
+     * app.log.1 before logrotate's compressed it) work too.
+     */
+    pub fn open(&self) -> io::Result<Box<dyn BufRead>> {
+        let mut reader: BufReader<Box<dyn Read>> = match self {
+            Source::Stdin => BufReader::new(Box::new(stdin())),
+            Source::File(path) => BufReader::new(Box::new(File::open(path)?)),
+        };
+
+        if reader.fill_buf()?.starts_with(&GZIP_MAGIC) {
+            // Our rotated logs are sometimes several gzip members glued
+            // together, which the plain GzDecoder would stop reading after
+            // the first of.
+            Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+        } else {
+            Ok(Box::new(reader))
+        }
+    }
+}
+
+
+// Only the rotated, compressed logs are picked out of directories. The live
+// log usually sits right next to them, and whatever's in it ends up in the
+// next one rotated out, so reading both would count it twice.
+fn is_log_file(path: &Path) -> bool {
+    match path.file_name().and_then(|name| name.to_str()) {
+        Some(name) => name.ends_with(".log.gz"),
+        None => false,
+    }
+}
+
+
+fn collect_log_files(dir: &Path, into: &mut Vec<PathBuf>) -> io::Result<()> {
+    for maybe_entry in dir.read_dir()? {
+        let path = maybe_entry?.path();
+        if path.is_dir() {
+            collect_log_files(&path, into)?;
+        } else if is_log_file(&path) {
+            into.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+
+// How far into a file we'll look for a timestamp before giving up on it
+const MAX_LINES_TO_DATE: usize = 1000;
+
+
+/**
+ * The earliest timestamp we can find near the start of a file.
+ *
+ * This is naive (in whatever timezone the logs were written in), but since
+ * every file is in the same timezone that's fine for comparing them.
+ */
+fn first_timestamp(path: &Path) -> Option<NaiveDateTime> {
+    let reader = Source::File(path.to_path_buf()).open().ok()?;
+    let mut lexer = Lexer::new();
+    reader.split(b'\n')
+        .take(MAX_LINES_TO_DATE)
+        .map_while(Result::ok)
+        .find_map(|line| {
+            parse_naive_timestamp(lexer.locate_parts(&line)?.timestamp)
+        })
+}
+
+
+/**
+ * Turns the command line arguments into the sources we should read.
+ *
+ * Files are read as given, directories are searched recursively for
+ * `*.log.gz` files, and `-` (or no arguments at all) means stdin.
+ *
+ * Files are put in the order their logs start in, since requests that span
+ * a log rotation only come out right if they're read in order. Rotated file
+ * names don't sort that way (app.log.2.gz is older than app.log.1.gz), so
+ * we peek at each file's first timestamp instead. Files we can't find a
+ * timestamp in go last.
+ */
+pub fn collect_sources(args: impl Iterator<Item = String>)
+        -> io::Result<Vec<Source>> {
+    let mut sources = Vec::new();
+    for arg in args {
+        let path = PathBuf::from(arg);
+        if path.as_os_str() == "-" {
+            sources.push(Source::Stdin);
+        } else if path.is_dir() {
+            // read_dir doesn't promise any particular order, so we sort to
+            // at least make runs over the same directory repeatable.
+            let mut paths = Vec::new();
+            collect_log_files(&path, &mut paths)?;
+            paths.sort();
+            sources.extend(paths.into_iter().map(Source::File));
+        } else {
+            sources.push(Source::File(path));
+        }
+    }
+
+    if sources.is_empty() {
+        sources.push(Source::Stdin);
+    }
+
+    // Peeking at stdin would eat it, so whoever's piping logs in is on their
+    // own for ordering.
+    if !sources.contains(&Source::Stdin) {
+        sources.sort_by_cached_key(|source| match source {
+            Source::File(path) => {
+                let started = first_timestamp(path);
+                (started.is_none(), started)
+            },
+            Source::Stdin => (true, None),
+        });
+    }
+
+    Ok(sources)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{BufRead, Write};
+    use std::path::PathBuf;
+
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+
+    use crate::input::{collect_sources, Source};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(
+            format!("shmeppy-log-input-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn read_lines(source: &Source) -> Vec<Vec<u8>> {
+        source.open().unwrap().split(b'\n').map(|line| line.unwrap()).collect()
+    }
+
+    #[test]
+    fn no_arguments() {
+        let sources = collect_sources(Vec::<String>::new().into_iter());
+        assert_eq!(sources.unwrap(), vec![Source::Stdin]);
+    }
+
+    #[test]
+    fn directory() {
+        let dir = scratch_dir("directory");
+        fs::create_dir(dir.join("nested")).unwrap();
+        fs::write(dir.join("b.log.gz"), b"").unwrap();
+        fs::write(dir.join("nested").join("a.log.gz"), b"").unwrap();
+        fs::write(dir.join("refresh.sh"), b"").unwrap();
+
+        // The live log's left for whoever's following it
+        fs::write(dir.join("app.log"), b"").unwrap();
+
+        let sources = collect_sources(
+            vec![dir.to_str().unwrap().to_string()].into_iter()).unwrap();
+        assert_eq!(sources, vec![
+            Source::File(dir.join("b.log.gz")),
+            Source::File(dir.join("nested").join("a.log.gz")),
+        ]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn chronological() {
+        let dir = scratch_dir("chronological");
+        let line = |day: u32| format!(
+            "shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) \
+             [INFO - 5/{}/2020 1:33:47 AM] Finished: 200 'OK'\n", day);
+
+        // Like logrotate leaves them, where bigger numbers are older. These
+        // aren't really gzipped, which is fine since open sniffs for that.
+        fs::write(dir.join("app.log"), line(28)).unwrap();
+        fs::write(dir.join("app.1.log.gz"), ["garbage\n", &line(27)].concat())
+            .unwrap();
+        fs::write(dir.join("app.2.log.gz"), line(26)).unwrap();
+        fs::write(dir.join("empty.log.gz"), b"").unwrap();
+
+        let sources = collect_sources(vec![
+            dir.to_str().unwrap().to_string(),
+            dir.join("app.log").to_str().unwrap().to_string(),
+        ].into_iter()).unwrap();
+        assert_eq!(sources, vec![
+            Source::File(dir.join("app.2.log.gz")),
+            Source::File(dir.join("app.1.log.gz")),
+            Source::File(dir.join("app.log")),
+            Source::File(dir.join("empty.log.gz")),
+        ]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn plain_and_gzipped() {
+        let dir = scratch_dir("plain_and_gzipped");
+
+        let plain = dir.join("plain.log");
+        fs::write(&plain, b"first\nsecond\n").unwrap();
+
+        // Two gzip members back to back, like logrotate sometimes leaves us
+        let gzipped = dir.join("gzipped.log.gz");
+        let mut contents = Vec::new();
+        for chunk in [&b"first\n"[..], &b"second\n"[..]].iter() {
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(chunk).unwrap();
+            contents.extend(encoder.finish().unwrap());
+        }
+        fs::write(&gzipped, contents).unwrap();
+
+        let expected = vec![b"first".to_vec(), b"second".to_vec()];
+        assert_eq!(read_lines(&Source::File(plain)), expected);
+        assert_eq!(read_lines(&Source::File(gzipped)), expected);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,495 @@
+use std::borrow::Cow;
+use std::fmt;
+
+use memchr::memchr;
+use serde::Deserialize;
+
+use crate::parse::skip_prefix;
+
+
+/**
+ * How important a log line is. These are syslog's severities, which is what
+ * the app's logger uses.
+ */
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy)]
+pub enum Level {
+    Debug,
+    Info,
+    Notice,
+    Warning,
+    Error,
+    Crit,
+    Alert,
+}
+
+
+impl Level {
+    pub fn from_bytes(raw: &[u8]) -> Option<Level> {
+        use Level::*;
+        Some(match raw {
+            b"DEBUG" => Debug,
+            b"INFO" => Info,
+            b"NOTICE" => Notice,
+            b"WARNING" => Warning,
+            b"ERROR" => Error,
+            b"CRIT" => Crit,
+            b"ALERT" => Alert,
+            _ => return None,
+        })
+    }
+}
+
+
+#[derive(Debug)]
+pub struct Parts<'a> {
+    pub uuid: &'a[u8],
+    pub timestamp: &'a[u8],
+
+    // The raw level (ie: INFO). See Level::from_bytes.
+    pub level: &'a[u8],
+
+    // The machine that emitted the line (ie: shmeppy-1)
+    pub host: &'a[u8],
+
+    // This is only ever Owned when a format escapes messages (ie: JSON
+    // lines with a quote in the message). Text formats always borrow.
+    pub message: Cow<'a, [u8]>,
+}
+
+
+impl<'a> Parts<'a> {
+    /**
+     * The parsed level. None if the level isn't one we know about.
+     */
+    pub fn level(&self) -> Option<Level> {
+        Level::from_bytes(self.level)
+    }
+}
+
+
+impl<'a> fmt::Display for Parts<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use std::str::from_utf8;
+        write!(f, "({}, {}, {}, {}, {})",
+               from_utf8(self.uuid).unwrap_or("<invalid utf8>"),
+               from_utf8(self.timestamp).unwrap_or("<invalid utf8>"),
+               from_utf8(self.level).unwrap_or("<invalid utf8>"),
+               from_utf8(self.host).unwrap_or("<invalid utf8>"),
+               from_utf8(&self.message).unwrap_or("<invalid utf8>"))
+    }
+}
+
+
+/**
+ * A layout our logs have been (or will be) written in.
+ *
+ * Implementations should be strict: returning None for a line that's "close
+ * enough" is what lets us tell formats apart, and what lets the Lexer notice
+ * when the logs stop looking like what it expected.
+ */
+pub trait LogFormat: Sync {
+    /**
+     * A short, stable name for the format.
+     */
+    fn name(&self) -> &'static str;
+
+    /**
+     * Finds the important bits of a log line, if it's in this format.
+     */
+    fn locate_parts<'a>(&self, log_line: &'a [u8]) -> Option<Parts<'a>>;
+}
+
+
+// Everything after the syslog tag looks like
+// `(c722d1d6-86e6-4117-b444-e146b013859d) [INFO - 4/25/2020 9:55:14 PM] msg`.
+// Lines logged outside of any request (ie: while the server is starting up)
+// don't have the parenthesized request ID at all.
+fn locate_app_parts<'a>(host: &'a [u8], rest: &'a [u8]) -> Option<Parts<'a>> {
+    let (uuid, rest) = if let Some(after_paren) = skip_prefix(b"(", rest) {
+        let right_paren = memchr(b')', after_paren)?;
+        (&after_paren[..right_paren],
+         skip_prefix(b" ", &after_paren[right_paren + 1..])?)
+    } else {
+        (&rest[..0], rest)
+    };
+
+    // The level runs up to the first dash, and the datetime is between that
+    // dash and the right square bracket. The message is everything after the
+    // right bracket.
+    let rest = skip_prefix(b"[", rest)?;
+    let dash = memchr(b'-', rest)?;
+    if dash == 0 || rest[dash - 1] != b' ' {
+        return None;
+    }
+    let right_bracket = dash + memchr(b']', &rest[dash..])?;
+
+    Some(Parts {
+        uuid,
+        timestamp: rest.get((dash + 2)..right_bracket)?,
+        level: &rest[..(dash - 1)],
+        host,
+        message: Cow::Borrowed(rest.get((right_bracket + 2)..).unwrap_or(&[])),
+    })
+}
+
+
+/**
+ * What the app logs through syslog look like today:
+ *
+ * `shmeppy-1 shmeppy-app: (uuid) [INFO - 4/25/2020 9:55:14 PM] msg`
+ */
+pub struct CurrentFormat;
+
+
+impl LogFormat for CurrentFormat {
+    fn name(&self) -> &'static str {
+        "current"
+    }
+
+    fn locate_parts<'a>(&self, log_line: &'a [u8]) -> Option<Parts<'a>> {
+        // Host, then the syslog tag (which always ends with a colon)
+        let host_end = memchr(b' ', log_line)?;
+        let tag_start = host_end + 1;
+        let tag_end = tag_start + memchr(b' ', &log_line[tag_start..])?;
+        if host_end == 0 || tag_end == tag_start ||
+                log_line[tag_end - 1] != b':' {
+            return None;
+        }
+
+        locate_app_parts(&log_line[..host_end], &log_line[(tag_end + 1)..])
+    }
+}
+
+
+/**
+ * What the app logs looked like before syslog stopped adding its own
+ * timestamp:
+ *
+ * `Oct  1 20:43:45 shmeppy-0 shmeppy-app: (uuid) [INFO - 10/1/2018 8:43:45 PM] msg`
+ */
+pub struct OldSyslogFormat;
+
+
+impl LogFormat for OldSyslogFormat {
+    fn name(&self) -> &'static str {
+        "old-syslog"
+    }
+
+    fn locate_parts<'a>(&self, log_line: &'a [u8]) -> Option<Parts<'a>> {
+        const MONTHS: [&[u8]; 12] = [
+            b"Jan", b"Feb", b"Mar", b"Apr", b"May", b"Jun",
+            b"Jul", b"Aug", b"Sep", b"Oct", b"Nov", b"Dec",
+        ];
+
+        // The syslog timestamp is always 15 characters wide, like
+        // `Oct  1 20:43:45` (the day is padded with a space).
+        let stamp = log_line.get(..16)?;
+        if !MONTHS.contains(&&stamp[..3]) || stamp[3] != b' ' ||
+                stamp[6] != b' ' || stamp[9] != b':' || stamp[12] != b':' ||
+                stamp[15] != b' ' {
+            return None;
+        }
+
+        CurrentFormat.locate_parts(&log_line[16..])
+    }
+}
+
+
+/**
+ * The structured format we'll move the app to eventually. One JSON object per
+ * line:
+ *
+ * `{"time":"4/25/2020 9:55:14 PM","level":"INFO","host":"shmeppy-1","requestId":"<uuid>","msg":"..."}`
+ *
+ * `time` uses the same stamp as the text formats so everything downstream of
+ * lexing keeps working unchanged. `requestId` may be missing or null.
+ */
+pub struct JsonLinesFormat;
+
+
+#[derive(Deserialize)]
+struct JsonLine<'a> {
+    #[serde(borrow)]
+    time: &'a str,
+
+    #[serde(borrow)]
+    level: &'a str,
+
+    #[serde(borrow)]
+    host: &'a str,
+
+    #[serde(borrow, rename = "requestId", default)]
+    request_id: Option<&'a str>,
+
+    #[serde(borrow)]
+    msg: Cow<'a, str>,
+}
+
+
+impl LogFormat for JsonLinesFormat {
+    fn name(&self) -> &'static str {
+        "json-lines"
+    }
+
+    fn locate_parts<'a>(&self, log_line: &'a [u8]) -> Option<Parts<'a>> {
+        // Cheap check so we don't spin up the JSON parser on text lines
+        if log_line.first() != Some(&b'{') {
+            return None;
+        }
+
+        let parsed: JsonLine<'a> = serde_json::from_slice(log_line).ok()?;
+        Some(Parts {
+            uuid: parsed.request_id.unwrap_or("").as_bytes(),
+            timestamp: parsed.time.as_bytes(),
+            level: parsed.level.as_bytes(),
+            host: parsed.host.as_bytes(),
+            message: match parsed.msg {
+                Cow::Borrowed(msg) => Cow::Borrowed(msg.as_bytes()),
+                Cow::Owned(msg) => Cow::Owned(msg.into_bytes()),
+            },
+        })
+    }
+}
+
+
+/**
+ * Every format we know about. Lexer tries them in this order.
+ */
+pub static FORMATS: [&dyn LogFormat; 3] = [
+    &CurrentFormat,
+    &OldSyslogFormat,
+    &JsonLinesFormat,
+];
+
+
+/**
+ * Finds the format a log line is in, if we know it.
+ */
+pub fn detect_format(log_line: &[u8]) -> Option<&'static dyn LogFormat> {
+    FORMATS.iter().copied()
+           .find(|format| format.locate_parts(log_line).is_some())
+}
+
+
+/**
+ * Lexes a single file's worth of lines.
+ *
+ * It detects the file's format from the first line that any format accepts
+ * and sticks with it, only going back to detection if a line doesn't fit (ie:
+ * the day we switch formats, which would otherwise happen mid-file).
+ */
+#[derive(Default)]
+pub struct Lexer {
+    format: Option<&'static dyn LogFormat>,
+}
+
+
+impl Lexer {
+    pub fn new() -> Lexer {
+        Lexer { format: None }
+    }
+
+    /**
+     * The format of the last line that lexed successfully.
+     */
+    pub fn format(&self) -> Option<&'static dyn LogFormat> {
+        self.format
+    }
+
+    pub fn locate_parts<'a>(&mut self, log_line: &'a [u8]) -> Option<Parts<'a>> {
+        if let Some(format) = self.format {
+            if let Some(parts) = format.locate_parts(log_line) {
+                return Some(parts);
+            }
+        }
+
+        for format in FORMATS.iter() {
+            if let Some(parts) = format.locate_parts(log_line) {
+                self.format = Some(*format);
+                return Some(parts);
+            }
+        }
+
+        None
+    }
+}
+
+
+/**
+ * Quickly find the important bits of a log message, in whatever format it's
+ * in.
+ *
+ * This returns slices of the original log line, so there's no copying
+ * that takes place (short of a JSON-lines message with escapes in it).
+ * Prefer a Lexer when reading a whole file, which won't have to try every
+ * format on every line.
+ */
+pub fn locate_parts(log_line: &[u8]) -> Option<Parts<'_>> {
+    Lexer::new().locate_parts(log_line)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::lex::locate_parts;
+
+    #[test]
+    fn bad_format() {
+        let log_line = b"not valid";
+        let result = locate_parts(log_line);
+        assert!(result.is_none());
+    }
+
+    #[test]
+    fn old_format() {
+        let log_line = b"Oct  1 20:43:45 shmeppy-0 shmeppy-app: (c4dfd175-c0ff-43d5-bd06-6366fd701030) [INFO - 10/1/2018 8:43:45 PM] Finished websocket: 1001 ''";
+        let result = locate_parts(log_line);
+        assert!(result.is_some());
+
+        let parts = result.unwrap();
+        assert_eq!(
+            parts.uuid,
+            &b"c4dfd175-c0ff-43d5-bd06-6366fd701030"[..]);
+        assert_eq!(
+            parts.timestamp,
+            &b"10/1/2018 8:43:45 PM"[..]);
+        assert_eq!(
+            parts.message,
+            &b"Finished websocket: 1001 ''"[..]);
+    }
+
+    #[test]
+    fn new_format() {
+        let log_line = b"shmeppy-1 shmeppy-app: (c722d1d6-86e6-4117-b444-e146b013859d) [INFO - 4/25/2020 9:55:14 PM] Committed 1 operation(s).";
+        let result = locate_parts(log_line);
+        assert!(result.is_some());
+
+        let parts = result.unwrap();
+        assert_eq!(
+            parts.uuid,
+            &b"c722d1d6-86e6-4117-b444-e146b013859d"[..]);
+        assert_eq!(
+            parts.timestamp,
+            &b"4/25/2020 9:55:14 PM"[..]);
+        assert_eq!(
+            parts.message,
+            &b"Committed 1 operation(s)."[..]);
+    }
+
+    mod formats {
+        use crate::lex::{
+            LogFormat, CurrentFormat, OldSyslogFormat, JsonLinesFormat,
+            Lexer, detect_format};
+
+        const OLD_SYSLOG: &[u8] = b"Oct  1 20:43:45 shmeppy-0 shmeppy-app: (c4dfd175-c0ff-43d5-bd06-6366fd701030) [INFO - 10/1/2018 8:43:45 PM] Finished websocket: 1001 ''";
+        const CURRENT: &[u8] = b"shmeppy-1 shmeppy-app: (c722d1d6-86e6-4117-b444-e146b013859d) [INFO - 4/25/2020 9:55:14 PM] Committed 1 operation(s).";
+        const JSON_LINES: &[u8] = br#"{"time":"4/25/2020 9:55:14 PM","level":"INFO","host":"shmeppy-1","requestId":"c722d1d6-86e6-4117-b444-e146b013859d","msg":"Committed 1 operation(s)."}"#;
+
+        #[test]
+        fn old_syslog() {
+            let parts = OldSyslogFormat.locate_parts(OLD_SYSLOG).unwrap();
+            assert_eq!(parts.uuid, &b"c4dfd175-c0ff-43d5-bd06-6366fd701030"[..]);
+            assert_eq!(parts.timestamp, &b"10/1/2018 8:43:45 PM"[..]);
+            assert_eq!(parts.level, &b"INFO"[..]);
+            assert_eq!(parts.host, &b"shmeppy-0"[..]);
+            assert_eq!(parts.message, &b"Finished websocket: 1001 ''"[..]);
+
+            // Two digit days aren't padded
+            assert!(OldSyslogFormat.locate_parts(b"Oct 10 20:43:45 shmeppy-0 shmeppy-app: (c4dfd175-c0ff-43d5-bd06-6366fd701030) [INFO - 10/10/2018 8:43:45 PM] Socket init complete").is_some());
+
+            assert!(OldSyslogFormat.locate_parts(CURRENT).is_none());
+            assert!(OldSyslogFormat.locate_parts(JSON_LINES).is_none());
+        }
+
+        #[test]
+        fn current() {
+            let parts = CurrentFormat.locate_parts(CURRENT).unwrap();
+            assert_eq!(parts.uuid, &b"c722d1d6-86e6-4117-b444-e146b013859d"[..]);
+            assert_eq!(parts.timestamp, &b"4/25/2020 9:55:14 PM"[..]);
+            assert_eq!(parts.level, &b"INFO"[..]);
+            assert_eq!(parts.host, &b"shmeppy-1"[..]);
+            assert_eq!(parts.message, &b"Committed 1 operation(s)."[..]);
+
+            assert!(CurrentFormat.locate_parts(OLD_SYSLOG).is_none());
+            assert!(CurrentFormat.locate_parts(JSON_LINES).is_none());
+        }
+
+        #[test]
+        fn current_without_request() {
+            let parts = CurrentFormat.locate_parts(b"shmeppy-1 shmeppy-app: [INFO - 4/25/2020 9:55:14 PM] Listening on port 8080").unwrap();
+            assert_eq!(parts.uuid, &b""[..]);
+            assert_eq!(parts.host, &b"shmeppy-1"[..]);
+            assert_eq!(parts.timestamp, &b"4/25/2020 9:55:14 PM"[..]);
+            assert_eq!(parts.message, &b"Listening on port 8080"[..]);
+        }
+
+        #[test]
+        fn json_lines() {
+            let parts = JsonLinesFormat.locate_parts(JSON_LINES).unwrap();
+            assert_eq!(parts.uuid, &b"c722d1d6-86e6-4117-b444-e146b013859d"[..]);
+            assert_eq!(parts.timestamp, &b"4/25/2020 9:55:14 PM"[..]);
+            assert_eq!(parts.level, &b"INFO"[..]);
+            assert_eq!(parts.host, &b"shmeppy-1"[..]);
+            assert_eq!(parts.message, &b"Committed 1 operation(s)."[..]);
+
+            // Escapes in the message get undone
+            let parts = JsonLinesFormat.locate_parts(br#"{"time":"4/25/2020 9:55:14 PM","level":"INFO","host":"shmeppy-1","requestId":null,"msg":"Client added to client DB: {\"gameId\":2323}"}"#).unwrap();
+            assert_eq!(parts.uuid, &b""[..]);
+            assert_eq!(parts.message, &br#"Client added to client DB: {"gameId":2323}"#[..]);
+
+            assert!(JsonLinesFormat.locate_parts(CURRENT).is_none());
+            assert!(JsonLinesFormat.locate_parts(br#"{"time":"4/25/2020 9:55:14 PM"}"#).is_none());
+        }
+
+        #[test]
+        fn detection() {
+            assert_eq!(detect_format(OLD_SYSLOG).unwrap().name(), "old-syslog");
+            assert_eq!(detect_format(CURRENT).unwrap().name(), "current");
+            assert_eq!(detect_format(JSON_LINES).unwrap().name(), "json-lines");
+            assert!(detect_format(b"not valid").is_none());
+            assert!(detect_format(b"").is_none());
+        }
+
+        #[test]
+        fn lexer_follows_format_changes() {
+            let mut lexer = Lexer::new();
+            assert!(lexer.format().is_none());
+
+            assert!(lexer.locate_parts(b"not valid").is_none());
+            assert!(lexer.format().is_none());
+
+            assert!(lexer.locate_parts(OLD_SYSLOG).is_some());
+            assert_eq!(lexer.format().unwrap().name(), "old-syslog");
+
+            assert!(lexer.locate_parts(b"not valid").is_none());
+            assert_eq!(lexer.format().unwrap().name(), "old-syslog");
+
+            assert!(lexer.locate_parts(CURRENT).is_some());
+            assert_eq!(lexer.format().unwrap().name(), "current");
+        }
+    }
+
+    mod level {
+        use crate::lex::{Level, locate_parts};
+
+        #[test]
+        fn from_bytes() {
+            assert_eq!(Level::from_bytes(b"DEBUG"), Some(Level::Debug));
+            assert_eq!(Level::from_bytes(b"ERROR"), Some(Level::Error));
+            assert_eq!(Level::from_bytes(b"debug"), None);
+            assert_eq!(Level::from_bytes(b""), None);
+        }
+
+        #[test]
+        fn ordering() {
+            assert!(Level::Debug < Level::Info);
+            assert!(Level::Info < Level::Error);
+        }
+
+        #[test]
+        fn from_parts() {
+            let parts = locate_parts(b"shmeppy-1 shmeppy-app: (357edc79-03fe-4787-910a-d2f14302348c) [DEBUG - 5/26/2020 1:36:23 AM] Waiting for client to complete closing handshake.").unwrap();
+            assert_eq!(parts.level(), Some(Level::Debug));
+        }
+    }
+}
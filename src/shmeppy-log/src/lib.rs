@@ -0,0 +1,12 @@
+pub mod lex;
+pub mod parse;
+pub mod ids;
+pub mod input;
+pub mod datestring;
+pub mod daterange;
+pub mod filter;
+pub mod feed;
+pub mod error;
+pub mod zones;
+pub mod event;
+pub mod registration;
@@ -0,0 +1,439 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Eq, PartialEq, Ord, PartialOrd, Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct UUID(pub u128);
+
+
+// Written the same way it shows up in the logs, so it can be grepped for
+impl fmt::Display for UUID {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hex = format!("{:032x}", self.0);
+        write!(f, "{}-{}-{}-{}-{}",
+               &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..])
+    }
+}
+
+
+// Only lowercase, since that's all the logs ever use
+#[allow(clippy::manual_range_contains)]
+pub fn is_ascii_hex_character(c: u8) -> bool {
+    (b'0' <= c && c <= b'9') || (b'a' <= c && c <= b'f')
+}
+
+
+pub fn parse_uuid(raw: &[u8]) -> Option<UUID> {
+    use std::mem::size_of;
+
+    let mut result = UUID(0);
+    let mut result_i = 0;
+
+    let mut raw_i = 0;
+    while result_i < size_of::<UUID>() && raw_i + 1 < raw.len() {
+        if is_ascii_hex_character(raw[raw_i]) &&
+                is_ascii_hex_character(raw[raw_i + 1]) {
+            let pair = unsafe {
+                // This is safe because we just verified both characters are
+                // within the lower ASCII range.
+                std::str::from_utf8_unchecked(&raw[raw_i..raw_i + 2])
+            };
+
+            // Likewise, we just verified that these are two hex characters,
+            // which will never overflow 128 bits (won't even overflow 8)...
+            let offset = (size_of::<UUID>() - result_i - 1) * 8;
+            result.0 |= u128::from_str_radix(pair, 16).unwrap() << offset;
+
+            result_i += 1;
+            raw_i += 2;
+        } else if raw[raw_i] == b'-' {
+            raw_i += 1;
+        } else {
+            raw_i += 2;
+        }
+    }
+
+    if result_i == size_of::<UUID>() && raw_i >= raw.len() {
+        Some(UUID(result.0))
+    } else {
+        None
+    }
+}
+
+
+/**
+ * Returns what's left of from after prefix, or None if from doesn't start
+ * with prefix.
+ */
+pub fn skip_prefix<'a>(prefix: &[u8], from: &'a [u8]) -> Option<&'a [u8]> {
+    if from.len() >= prefix.len() && prefix == &from[..prefix.len()] {
+        Some(&from[prefix.len()..])
+    } else {
+        None
+    }
+}
+
+
+// Extracts a base 10 ascii-encoded integer from the very start of raw. So
+// b"12 hello" would get 12 extracted, but b" 12 hello" would give None.
+pub fn extract_integer<T: std::str::FromStr>(raw: &[u8]) -> Option<T> {
+    let mut i = 0;
+    while i < raw.len() {
+        if raw[i] < b'0' || raw[i] > b'9' {
+            break;
+        }
+
+        i += 1;
+    }
+
+    let integer_as_str = unsafe {
+        std::str::from_utf8_unchecked(&raw[..i])
+    };
+
+    integer_as_str.parse::<T>().ok()
+}
+
+
+// Parses a run of 1 to max_digits ASCII digits at the start of raw, giving
+// back the number and what's left after it.
+fn parse_digits(raw: &[u8], max_digits: usize) -> Option<(u32, &[u8])> {
+    let n = raw.iter().take(max_digits).take_while(|c| c.is_ascii_digit())
+        .count();
+    if n == 0 {
+        return None;
+    }
+
+    let value = raw[..n].iter()
+        .fold(0, |value, c| value * 10 + u32::from(c - b'0'));
+    Some((value, &raw[n..]))
+}
+
+
+// Like parse_digits, but the digits have to be followed by separator (which
+// is skipped over).
+fn parse_digits_until(raw: &[u8], max_digits: usize, separator: u8)
+        -> Option<(u32, &[u8])> {
+    let (value, rest) = parse_digits(raw, max_digits)?;
+    match rest.split_first() {
+        Some((&c, rest)) if c == separator => Some((value, rest)),
+        _ => None,
+    }
+}
+
+
+/**
+ * Parses a timestamp like 4/25/2020 9:55:14 PM, without any regard for
+ * timezones.
+ *
+ * This is about as hot as code gets in here (it runs on every line a
+ * collector cares about), so rather than going through chrono's format
+ * strings this walks the bytes directly. See parse_timestamp_slow for the
+ * version this needs to agree with.
+ */
+pub fn parse_naive_timestamp(raw: &[u8]) -> Option<chrono::NaiveDateTime> {
+    let (month, rest) = parse_digits_until(raw, 2, b'/')?;
+    let (day, rest) = parse_digits_until(rest, 2, b'/')?;
+    let (year, rest) = parse_digits_until(rest, 4, b' ')?;
+    let (hour, rest) = parse_digits_until(rest, 2, b':')?;
+    let (minute, rest) = parse_digits_until(rest, 2, b':')?;
+    let (second, rest) = parse_digits_until(rest, 2, b' ')?;
+
+    let is_pm = match rest {
+        b"AM" | b"am" => false,
+        b"PM" | b"pm" => true,
+        _ => return None,
+    };
+    let hour = match (hour, is_pm) {
+        (1..=11, false) => hour,
+        (12, false) => 0,
+        (1..=11, true) => hour + 12,
+        (12, true) => 12,
+        _ => return None,
+    };
+
+    chrono::NaiveDate::from_ymd_opt(year as i32, month, day)?
+        .and_hms_opt(hour, minute, second)
+}
+
+
+pub fn parse_timestamp(raw: &[u8]) -> Option<chrono::DateTime<chrono::Utc>> {
+    // Skips the timezone lookup entirely
+    parse_naive_timestamp(raw)
+        .map(|naive| chrono::DateTime::from_utc(naive, chrono::Utc))
+}
+
+
+/**
+ * The chrono based parser parse_naive_timestamp replaced. It's kept around
+ * as the reference the fast one is tested and benchmarked against.
+ */
+pub fn parse_timestamp_slow(raw: &[u8])
+        -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::offset::TimeZone;
+    chrono::Utc.datetime_from_str(
+        std::str::from_utf8(raw).ok()?,
+        "%-m/%-d/%Y %-I:%M:%S %p").ok() // ok? ok
+}
+
+
+/**
+ * Parses a timestamp that was written as local time in tz.
+ *
+ * When the clocks go back an hour we can't tell which of the two repeated
+ * hours a timestamp was in, so we pick the first. Timestamps in the hour
+ * skipped when the clocks go forward can't happen, so they get None.
+ */
+pub fn parse_timestamp_in(raw: &[u8], tz: &chrono_tz::Tz)
+        -> Option<chrono::DateTime<chrono::Utc>> {
+    use chrono::offset::TimeZone;
+    if *tz == chrono_tz::Tz::UTC {
+        return parse_timestamp(raw);
+    }
+
+    let naive = parse_naive_timestamp(raw)?;
+    let local = tz.from_local_datetime(&naive).earliest()?;
+    Some(local.with_timezone(&chrono::Utc))
+}
+
+
+#[cfg(test)]
+mod tests {
+    mod timestamp {
+        use chrono::Utc;
+        use chrono::offset::TimeZone;
+        use crate::parse::parse_timestamp;
+
+        #[test]
+        fn standard_format() {
+            let raw_datetime = b"4/25/2020 9:55:14 PM";
+            let datetime = parse_timestamp(raw_datetime);
+            assert!(datetime.is_some());
+            assert_eq!(datetime.unwrap(),
+                       Utc.ymd(2020, 4, 25).and_hms(21, 55, 14));
+        }
+
+        #[test]
+        fn missing_am_pm() {
+            let raw_datetime = b"4/25/2020 9:55:14";
+            let datetime = parse_timestamp(raw_datetime);
+            assert!(datetime.is_none());
+        }
+
+        #[test]
+        fn empty() {
+            let raw_datetime = b"";
+            let datetime = parse_timestamp(raw_datetime);
+            assert!(datetime.is_none());
+        }
+
+        #[test]
+        fn bad_utf8() {
+            // There's no way to tell whether the UTF8 decoder is correctly
+            // failing or whether it fails at the parse step when it realizes
+            // this is most certainly not a date. Downside of using Option as
+            // a carrier of the error status.
+            let raw_datetime = b"\xc3\x28";
+            let datetime = parse_timestamp(raw_datetime);
+            assert!(datetime.is_none());
+        }
+
+        #[test]
+        fn matches_chrono() {
+            use crate::parse::parse_timestamp_slow;
+
+            // Every month, every hour and both halves of the day, along with
+            // days and minutes that are and aren't zero padded.
+            let mut raws = Vec::new();
+            for month in 1..=12 {
+                for &day in &[1, 9, 10, 28, 29, 30, 31] {
+                    for hour in 1..=12 {
+                        for &(minute, second) in &[(0, 0), (5, 9), (59, 59)] {
+                            for meridiem in &["AM", "PM"] {
+                                raws.push(format!(
+                                    "{}/{}/2020 {}:{:02}:{:02} {}", month,
+                                    day, hour, minute, second, meridiem));
+                            }
+                        }
+                    }
+                }
+            }
+
+            // And a bunch of ways for it to go wrong
+            raws.extend(vec![
+                "", "4", "4/", "4/25", "4/25/2020", "4/25/2020 9:55:14",
+                "4/25/2020 9:55:14 XM", "4/25/2020 9:55:14 PMX",
+                "4/25/2020 0:55:14 PM", "4/25/2020 13:55:14 PM",
+                "13/25/2020 9:55:14 PM", "0/25/2020 9:55:14 PM",
+                "2/30/2020 9:55:14 PM", "2/29/2019 9:55:14 PM",
+                "4/25/2020 9:60:14 PM", "4/25/2020 9:55:61 PM",
+                "a/25/2020 9:55:14 PM", "4/25/2020 9:55:14 \u{e9}M",
+                "04/05/2020 09:05:04 AM", "4/25/2020 12:00:00 AM",
+                "4/25/2020 12:00:00 PM", "4/25/2020 9:55:14 pm",
+            ].into_iter().map(String::from));
+
+            for raw in raws {
+                assert_eq!(parse_timestamp(raw.as_bytes()),
+                           parse_timestamp_slow(raw.as_bytes()),
+                           "disagree on {:?}", raw);
+            }
+        }
+
+        #[test]
+        fn dst_transitions() {
+            use chrono_tz::America::Los_Angeles;
+            use crate::parse::parse_timestamp_in;
+
+            assert_eq!(
+                parse_timestamp_in(b"5/29/2020 7:00:00 PM", &Los_Angeles),
+                Some(Utc.ymd(2020, 5, 30).and_hms(2, 0, 0)));
+            assert_eq!(
+                parse_timestamp_in(b"1/29/2020 7:00:00 PM", &Los_Angeles),
+                Some(Utc.ymd(2020, 1, 30).and_hms(3, 0, 0)));
+
+            // 2:30 AM never happened on the day the clocks went forward
+            assert_eq!(
+                parse_timestamp_in(b"3/8/2020 2:30:00 AM", &Los_Angeles),
+                None);
+
+            // 1:30 AM happened twice on the day they went back
+            assert_eq!(
+                parse_timestamp_in(b"11/1/2020 1:30:00 AM", &Los_Angeles),
+                Some(Utc.ymd(2020, 11, 1).and_hms(8, 30, 0)));
+        }
+    }
+
+    mod uuid {
+        use crate::parse::{UUID, parse_uuid};
+
+        #[test]
+        fn standard_format() {
+            let raw_uuid = b"72aee7fd-3842-49cf-a196-be874a72ed9c";
+            let uuid = parse_uuid(raw_uuid);
+            assert!(uuid.is_some());
+
+            assert_eq!(uuid.unwrap(),
+                       UUID(152440156471620327380106519705898118556));
+        }
+
+        #[test]
+        fn missing_dashes() {
+            let raw_uuid = b"72aee7fd384249cfa196be874a72ed9c";
+            let uuid = parse_uuid(raw_uuid);
+            assert!(uuid.is_some());
+
+            assert_eq!(uuid.unwrap(),
+                       UUID(152440156471620327380106519705898118556));
+        }
+
+        #[test]
+        fn empty() {
+            let raw_uuid = b"";
+            assert!(parse_uuid(raw_uuid).is_none());
+        }
+
+        #[test]
+        fn non_dashes() {
+            let raw_uuid = b"72aee7fd_3842-49cf-a196-be874a72ed9c";
+            assert!(parse_uuid(raw_uuid).is_none());
+        }
+
+        #[test]
+        fn truncated() {
+            let raw_uuid = b"72aee7fd-3842-49cf-a196-be874a72ed";
+            assert!(parse_uuid(raw_uuid).is_none());
+        }
+
+        #[test]
+        fn too_long() {
+            let raw_uuid = b"72aee7fd-3842-49cf-a196-be874a72ed9c\
+                             72aee7fd-3842-49cf-a196-be874a72ed9c";
+            assert!(parse_uuid(raw_uuid).is_none());
+        }
+
+        #[test]
+        fn displayed() {
+            let raw_uuid = "00aee7fd-3842-49cf-a196-be874a72ed9c";
+            let uuid = parse_uuid(raw_uuid.as_bytes()).unwrap();
+            assert_eq!(uuid.to_string(), raw_uuid);
+        }
+    }
+
+    mod skip_prefix {
+        use crate::parse::skip_prefix;
+
+        #[test]
+        fn prefix_present() {
+            let text = b"HelloWorld";
+            let prefix = b"Hello";
+            let result = skip_prefix(prefix, text);
+            assert!(result.is_some());
+            assert_eq!(result.unwrap(), b"World");
+            assert_eq!(result.unwrap().as_ptr(), text[5..].as_ptr());
+        }
+
+        #[test]
+        fn prefix_missing() {
+            let text = b"HelloWorld";
+            let prefix = b"Goodbye";
+            let result = skip_prefix(prefix, text);
+            assert!(result.is_none());
+        }
+
+        #[test]
+        fn empty_prefix() {
+            let text = b"HelloWorld";
+            let prefix = b"";
+            let result = skip_prefix(prefix, text);
+            assert!(result.is_some());
+            assert_eq!(result.unwrap(), b"HelloWorld");
+            assert_eq!(result.unwrap().as_ptr(), text.as_ptr());
+        }
+
+        #[test]
+        fn empty_text() {
+            let text = b"";
+            let prefix = b"Hello";
+            let result = skip_prefix(prefix, text);
+            assert!(result.is_none());
+        }
+
+        #[test]
+        fn empty_both() {
+            let text = b"";
+            let prefix = b"";
+            let result = skip_prefix(prefix, text);
+            assert!(result.is_some());
+            assert_eq!(result.unwrap(), b"");
+            assert_eq!(result.unwrap().as_ptr(), text.as_ptr());
+        }
+    }
+
+    mod extract_integer {
+        use crate::parse::extract_integer;
+
+        #[test]
+        fn simple_u8() {
+            assert_eq!(extract_integer::<u8>(b"12"), Some(12));
+            assert_eq!(extract_integer::<u8>(b"12 hello"), Some(12));
+            assert_eq!(extract_integer::<u8>(b"0 hello"), Some(0));
+            assert_eq!(extract_integer::<u8>(b"255 hello"), Some(255));
+        }
+
+        #[test]
+        fn overflow_u8() {
+            assert_eq!(extract_integer::<u8>(b"256 hello"), None);
+        }
+
+        #[test]
+        fn space_in_front() {
+            assert_eq!(extract_integer::<u8>(b" 12 hello"), None);
+        }
+
+        #[test]
+        fn empty() {
+            assert_eq!(extract_integer::<u8>(b""), None);
+        }
+    }
+}
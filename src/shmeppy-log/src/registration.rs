@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::datestring::DateString;
+use crate::error::Stage;
+use crate::lex::Parts;
+use crate::parse::{UUID, parse_uuid, skip_prefix, extract_integer};
+use crate::zones::Zones;
+
+
+/**
+ * Counts the registrations that completed successfully on each day.
+ *
+ * A registration is a `POST /api/account/register/complete/` request that
+ * finished with a 200.
+ */
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RegistrationCollector {
+    // Maps dates to number of registrations on that day
+    counts: BTreeMap<DateString, usize>,
+
+    // Stores any "complete registration" request IDs that we've seen start
+    // but not finish
+    pending: BTreeSet<UUID>,
+
+    // The first successful finish of requests we didn't see start, along
+    // with the day it would be counted on. Any of these could be the end of
+    // a registration pending in an earlier chunk of logs (see merge). Ones
+    // that didn't succeed could never be counted, so they aren't kept.
+    orphans: BTreeMap<UUID, DateString>,
+
+    #[serde(skip)]
+    zones: Zones,
+}
+
+
+fn is_start_of_registration_request(msg: &[u8]) -> bool {
+    skip_prefix(b"Started: POST /api/account/register/complete/", msg).is_some()
+}
+
+
+/**
+ * The status code of a "Finished: 200 'OK'" line, if that's what msg is.
+ */
+pub fn get_request_status(msg: &[u8]) -> Option<u16> {
+    extract_integer(skip_prefix(b"Finished: ", msg)?)
+}
+
+
+impl RegistrationCollector {
+    pub fn new() -> RegistrationCollector {
+        RegistrationCollector::with_zones(Zones::default())
+    }
+
+    pub fn with_zones(zones: Zones) -> RegistrationCollector {
+        RegistrationCollector {
+            counts: BTreeMap::new(),
+            pending: BTreeSet::new(),
+            orphans: BTreeMap::new(),
+            zones,
+        }
+    }
+
+    /**
+     * Gives back the stage the line was rejected at, if it's one we care
+     * about and couldn't make sense of.
+     */
+    pub fn update(&mut self, parts: &Parts) -> Result<(), Stage> {
+        if is_start_of_registration_request(&parts.message) {
+            self.pending.insert(
+                parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?);
+        } else if let Some(code) = get_request_status(&parts.message) {
+            let uuid = parse_uuid(parts.uuid).ok_or(Stage::ParseUuid)?;
+            let date = if code == 200 {
+                Some(self.zones.day(parts.timestamp)
+                     .ok_or(Stage::ParseTimestamp)?)
+            } else {
+                None
+            };
+
+            match (self.pending.remove(&uuid), date) {
+                (true, Some(date)) => *self.counts.entry(date).or_insert(0) += 1,
+                (false, Some(date)) => {
+                    self.orphans.entry(uuid).or_insert(date);
+                },
+                (_, None) => (),
+            }
+        }
+
+        Ok(())
+    }
+
+    /**
+     * Folds in a collector that was fed the lines right after the ones this
+     * one was fed.
+     */
+    pub fn merge(&mut self, later: RegistrationCollector) {
+        for (uuid, date) in later.orphans {
+            if self.pending.remove(&uuid) {
+                *self.counts.entry(date).or_insert(0) += 1;
+            } else {
+                self.orphans.entry(uuid).or_insert(date);
+            }
+        }
+
+        for (date, count) in later.counts {
+            *self.counts.entry(date).or_insert(0) += count;
+        }
+        self.pending.extend(later.pending);
+    }
+
+    /**
+     * Throws away the orphans. Once everything before this collector's lines
+     * has been merged in, nothing will ever claim them.
+     */
+    pub fn forget_orphans(&mut self) {
+        self.orphans.clear();
+    }
+
+    pub fn into_counts(self) -> BTreeMap<DateString, usize> {
+        self.counts
+    }
+}
+
+
+impl Default for RegistrationCollector {
+    fn default() -> RegistrationCollector {
+        RegistrationCollector::new()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use crate::datestring::DateString;
+    use crate::lex::locate_parts;
+    use crate::registration::RegistrationCollector;
+
+    #[test]
+    fn standard_use() {
+        let lines: Vec<&[u8]> = vec![
+            // Succeeds
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/26/2020 3:33:19 PM] Started: POST /api/account/register/complete/ 1.1",
+            // Fails
+            b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/account/register/complete/ 1.1",
+            // Not a registration at all
+            b"shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:20 PM] Started: POST /api/auth/login 1.1",
+            b"shmeppy-1 shmeppy-app: (45e0e69c-ddd9-4443-abab-f3b46e47a62b) [INFO - 5/26/2020 3:33:21 PM] Finished: 200 'OK'",
+            b"shmeppy-1 shmeppy-app: (ce3f74d8-5e3c-48de-8411-d0663861bed8) [INFO - 5/26/2020 3:33:21 PM] Finished: 400 'Bad Request'",
+            // Finishes the day after it started
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:01 AM] Finished: 200 'OK'",
+            // Duplicate finish shouldn't be double counted
+            b"shmeppy-1 shmeppy-app: (d2deee0c-9fd8-446c-9506-be65bbac5206) [INFO - 5/27/2020 12:00:02 AM] Finished: 200 'OK'",
+        ];
+
+        let collect = |lines: &[&[u8]]| {
+            let mut collector = RegistrationCollector::new();
+            for line in lines {
+                collector.update(&locate_parts(line).unwrap()).unwrap();
+            }
+            collector
+        };
+
+        // Only the login's finish could be a registration from an earlier
+        // chunk. The failed one couldn't ever be counted.
+        assert_eq!(collect(&lines[2..5]).orphans.len(), 1);
+
+        assert_eq!(
+            collect(&lines).into_counts().into_iter().collect::<Vec<_>>(),
+            vec![(DateString(*b"2020-05-27"), 1)]);
+
+        for split in 0..=lines.len() {
+            let mut merged = collect(&lines[..split]);
+            merged.forget_orphans();
+            merged.merge(collect(&lines[split..]));
+            assert_eq!(
+                merged.into_counts().into_iter().collect::<Vec<_>>(),
+                vec![(DateString(*b"2020-05-27"), 1)],
+                "split at {}", split);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+use chrono::{Date, DateTime, Utc};
+use chrono::offset::TimeZone;
+use chrono_tz::Tz;
+
+use crate::datestring::DateString;
+use crate::parse::parse_timestamp_in;
+
+
+/**
+ * The timezone the server wrote its logs in, and the one we bucket reports
+ * by.
+ *
+ * These are kept separate so that, say, a server logging in UTC can still
+ * have its Friday-night US games counted on Friday.
+ */
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Zones {
+    pub log: Tz,
+    pub report: Tz,
+}
+
+
+impl Default for Zones {
+    fn default() -> Zones {
+        Zones {
+            log: Tz::UTC,
+            report: Tz::UTC,
+        }
+    }
+}
+
+
+impl Zones {
+    pub fn parse(&self, raw: &[u8]) -> Option<DateTime<Utc>> {
+        parse_timestamp_in(raw, &self.log)
+    }
+
+    /**
+     * The reporting day a raw timestamp falls on.
+     */
+    pub fn day(&self, raw: &[u8]) -> Option<DateString> {
+        if self.log == self.report {
+            // The date's already sitting right there in the timestamp, so
+            // skip parsing the whole thing.
+            DateString::from_timestamp(raw)
+        } else {
+            Some(DateString::from_date(&self.report_date(self.parse(raw)?)))
+        }
+    }
+
+    pub fn report_date(&self, instant: DateTime<Utc>) -> Date<Tz> {
+        instant.with_timezone(&self.report).date()
+    }
+}
+
+
+/**
+ * Parses a timezone name, as given to --log-tz or --report-tz.
+ */
+pub fn parse_zone(raw: &str) -> Result<Tz, String> {
+    raw.parse().map_err(|_| format!("unknown timezone {}", raw))
+}
+
+
+/**
+ * The first moment of day. That's usually midnight, but some places skip
+ * midnight entirely when DST starts.
+ */
+pub fn start_of_day(day: &Date<Tz>) -> DateTime<Utc> {
+    let tz = day.timezone();
+    (0..24)
+        .find_map(|hour| tz.from_local_datetime(
+            &day.naive_local().and_hms(hour, 0, 0)).earliest())
+        .expect("every day has some hour in it")
+        .with_timezone(&Utc)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+    use chrono::offset::TimeZone;
+    use chrono_tz::Tz;
+    use chrono_tz::America::{Los_Angeles, Sao_Paulo};
+
+    use crate::datestring::DateString;
+    use crate::zones::{Zones, parse_zone, start_of_day};
+
+    #[test]
+    fn day_in_report_zone() {
+        let zones = Zones { log: Tz::UTC, report: Los_Angeles };
+
+        // 2 AM UTC on Saturday is still Friday evening in California
+        assert_eq!(zones.day(b"5/30/2020 2:00:00 AM"),
+                   Some(DateString(*b"2020-05-29")));
+        assert_eq!(zones.day(b"5/30/2020 8:00:00 AM"),
+                   Some(DateString(*b"2020-05-30")));
+        assert_eq!(zones.day(b"garbage"), None);
+    }
+
+    #[test]
+    fn same_zones() {
+        let zones = Zones::default();
+        assert_eq!(zones.day(b"5/30/2020 2:00:00 AM"),
+                   Some(DateString(*b"2020-05-30")));
+    }
+
+    #[test]
+    fn parse_in_log_zone() {
+        let zones = Zones { log: Los_Angeles, report: Tz::UTC };
+        assert_eq!(zones.parse(b"5/29/2020 7:00:00 PM"),
+                   Some(Utc.ymd(2020, 5, 30).and_hms(2, 0, 0)));
+        assert_eq!(zones.day(b"5/29/2020 7:00:00 PM"),
+                   Some(DateString(*b"2020-05-30")));
+    }
+
+    #[test]
+    fn zone_names() {
+        assert_eq!(parse_zone("America/Los_Angeles"), Ok(Los_Angeles));
+        assert!(parse_zone("Mars/Olympus_Mons").is_err());
+    }
+
+    #[test]
+    fn start_of_day_across_dst() {
+        assert_eq!(start_of_day(&Los_Angeles.ymd(2020, 3, 8)),
+                   Utc.ymd(2020, 3, 8).and_hms(8, 0, 0));
+        assert_eq!(start_of_day(&Los_Angeles.ymd(2020, 3, 9)),
+                   Utc.ymd(2020, 3, 9).and_hms(7, 0, 0));
+
+        // Brazil used to start DST at midnight, so that day starts at 1 AM
+        assert_eq!(start_of_day(&Sao_Paulo.ymd(2018, 11, 4)),
+                   Utc.ymd(2018, 11, 4).and_hms(3, 0, 0));
+    }
+}